@@ -0,0 +1,459 @@
+use crate::interpreter::builtin_constant;
+use crate::syntax::{Expr, ExprKind, Stmt, StmtKind};
+use crate::tokens::Token;
+use crate::visitor::Visitor;
+use std::cmp::Ordering;
+use std::collections::HashSet as Set;
+use std::slice;
+use thiserror::Error;
+
+/// A problem found in a program without running it.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    /// A variable is used but never assigned.
+    #[error("[line {line}] variable '{name}' is never assigned")]
+    UndefinedVariable {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+    /// A literal jump target is past the end of the program.
+    #[error("[line {line}] jump to statement {target}, which does not exist")]
+    BadJumpTarget {
+        line: usize,
+        column: usize,
+        target: u32,
+    },
+}
+
+impl AnalysisError {
+    /// The line and column at which the problem was found.
+    pub fn position(&self) -> (usize, usize) {
+        match *self {
+            AnalysisError::UndefinedVariable { line, column, .. }
+            | AnalysisError::BadJumpTarget { line, column, .. } => (line, column),
+        }
+    }
+}
+
+/// Errors are ordered by position, and then by message.
+impl Ord for AnalysisError {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.position()
+            .cmp(&other.position())
+            .then_with(|| self.to_string().cmp(&other.to_string()))
+    }
+}
+
+impl PartialOrd for AnalysisError {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A statement which can never be executed. Warnings are ordered by position.
+#[derive(Error, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[error("[line {line}] unreachable statement")]
+pub struct AnalysisWarning {
+    /// The line on which the statement starts.
+    pub line: usize,
+    /// The column at which the statement starts.
+    pub column: usize,
+}
+
+/// Find statements directly after an unconditional `goto`, `return` or `halt`
+/// which are not the target of any jump.
+///
+/// A label is always considered a target, as is any statement
+/// whose position appears as a literal target in the program.
+/// Statements further on are not reported, even if they are just as unreachable.
+/// The warnings are sorted by position.
+pub fn unreachable_statements(stmts: &[Stmt]) -> Vec<AnalysisWarning> {
+    let mut targets = Set::new();
+    jump_targets(stmts, &mut targets);
+    let mut warnings = Vec::new();
+    unreachable_in(stmts, &targets, &mut warnings);
+    warnings.sort();
+    warnings
+}
+
+/// Collect the positions of all literal jump targets.
+fn jump_targets(stmts: &[Stmt], targets: &mut Set<u32>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::Goto(target) | StmtKind::Call(target) => targets.extend(literal(target)),
+            StmtKind::IfThenElse(_, iftrue, iffalse) => {
+                jump_targets(slice::from_ref(iftrue), targets);
+                jump_targets(slice::from_ref(iffalse), targets);
+            }
+            StmtKind::While(_, body) | StmtKind::Block(body) => jump_targets(body, targets),
+            StmtKind::Switch(_, cases, default) => {
+                for body in switch_bodies(cases, default) {
+                    jump_targets(body, targets);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Find literal `goto`, `call` and `if` targets outside `0..=len`, where `len` is the
+/// number of top-level statements. A jump to `len` ends the program.
+///
+/// Computed targets, including labels, are only known when the program runs.
+/// The errors are sorted by position.
+pub fn bad_jump_targets(stmts: &[Stmt]) -> Vec<AnalysisError> {
+    let mut errors = Vec::new();
+    bad_jumps_in(stmts, stmts.len(), &mut errors);
+    errors.sort();
+    errors
+}
+
+/// Report the literal targets in `stmts` and their nested bodies which are greater than `len`.
+fn bad_jumps_in(stmts: &[Stmt], len: usize, errors: &mut Vec<AnalysisError>) {
+    for stmt in stmts {
+        let targets = match &stmt.kind {
+            StmtKind::Goto(target) | StmtKind::Call(target) => vec![target],
+            StmtKind::IfThenElse(_, iftrue, iffalse) => {
+                bad_jumps_in(slice::from_ref(iftrue), len, errors);
+                bad_jumps_in(slice::from_ref(iffalse), len, errors);
+                vec![]
+            }
+            StmtKind::While(_, body) | StmtKind::Block(body) => {
+                bad_jumps_in(body, len, errors);
+                vec![]
+            }
+            StmtKind::Switch(_, cases, default) => {
+                switch_bodies(cases, default).for_each(|body| bad_jumps_in(body, len, errors));
+                vec![]
+            }
+            _ => vec![],
+        };
+        for target in targets {
+            match literal(target) {
+                Some(position) if position as usize > len => {
+                    errors.push(AnalysisError::BadJumpTarget {
+                        line: target.span.start_line,
+                        column: target.span.start_col,
+                        target: position,
+                    })
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// The value of an integer literal.
+fn literal(e: &Expr) -> Option<u32> {
+    match e.kind {
+        ExprKind::Val(val) => Some(val),
+        _ => None,
+    }
+}
+
+/// Report unreachable statements in `stmts` and their nested bodies.
+/// Only top-level positions can be jump targets.
+fn unreachable_in(stmts: &[Stmt], targets: &Set<u32>, warnings: &mut Vec<AnalysisWarning>) {
+    for (i, pair) in stmts.windows(2).enumerate() {
+        let (jump, next) = (&pair[0], &pair[1]);
+        let unconditional = matches!(
+            jump.kind,
+            StmtKind::Goto(_) | StmtKind::Return | StmtKind::Halt
+        );
+        let target = matches!(next.kind, StmtKind::Label(_)) || targets.contains(&(i as u32 + 1));
+        if unconditional && !target {
+            warnings.push(AnalysisWarning {
+                line: next.span.start_line,
+                column: next.span.start_col,
+            });
+        }
+    }
+    for stmt in stmts {
+        match &stmt.kind {
+            StmtKind::While(_, body) | StmtKind::Block(body) => {
+                unreachable_in(body, &Set::new(), warnings)
+            }
+            StmtKind::Switch(_, cases, default) => {
+                for body in switch_bodies(cases, default) {
+                    unreachable_in(body, &Set::new(), warnings);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The case bodies of a switch, followed by its default body.
+fn switch_bodies<'a>(
+    cases: &'a [(u32, Vec<Stmt>)],
+    default: &'a Option<Vec<Stmt>>,
+) -> impl Iterator<Item = &'a [Stmt]> {
+    cases
+        .iter()
+        .map(|(_, body)| body.as_slice())
+        .chain(default.as_deref())
+}
+
+/// Find uses of variables which are not assigned anywhere in the program.
+///
+/// Jumps make the order of execution hard to predict,
+/// so a variable assigned anywhere, or a label used as a goto target, is never reported.
+/// Neither is a predefined constant such as `MAX_U32`.
+/// The errors are sorted by position.
+pub fn undefined_variables(stmts: &[Stmt]) -> Vec<AnalysisError> {
+    let usage = variable_usage(stmts);
+    let defined = |name: &String| usage.writes.contains(name) || usage.labels.contains(name);
+    let mut errors: Vec<_> = usage
+        .uses
+        .iter()
+        .filter(|var| !defined(&var.lexeme) && builtin_constant(&var.lexeme).is_none())
+        .map(|var| AnalysisError::UndefinedVariable {
+            name: var.lexeme.clone(),
+            line: var.line,
+            column: var.column,
+        })
+        .collect();
+    errors.sort();
+    errors
+}
+
+/// The names of the variables a program reads and writes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VariableUsage {
+    /// Variables used in an expression, including labels used as jump targets.
+    pub reads: Set<String>,
+    /// Variables assigned, with or without `const`.
+    pub writes: Set<String>,
+    /// Labels defined by the program.
+    pub labels: Set<String>,
+    /// Every use of a variable, in the order of the program.
+    uses: Vec<Token>,
+}
+
+/// Collect the variables read and written anywhere in the program, including nested bodies.
+pub fn variable_usage(stmts: &[Stmt]) -> VariableUsage {
+    let mut usage = VariableUsage::default();
+    usage.walk(stmts);
+    usage
+}
+
+impl VariableUsage {
+    fn walk(&mut self, stmts: &[Stmt]) {
+        stmts.iter().for_each(|stmt| self.visit_stmt(stmt));
+    }
+}
+
+impl Visitor<()> for VariableUsage {
+    fn visit_stmt(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Assignment(var, e) | StmtKind::ConstAssignment(var, e) => {
+                self.writes.insert(var.lexeme.clone());
+                self.visit_expr(e);
+            }
+            StmtKind::Goto(e)
+            | StmtKind::Assert(_, e)
+            | StmtKind::Print(e)
+            | StmtKind::Expression(e)
+            | StmtKind::Call(e) => self.visit_expr(e),
+            StmtKind::Store(lhs, rhs, _) => {
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            }
+            StmtKind::IfThenElse(cond, iftrue, iffalse) => {
+                self.visit_expr(cond);
+                self.visit_stmt(iftrue);
+                self.visit_stmt(iffalse);
+            }
+            StmtKind::While(cond, body) => {
+                self.visit_expr(cond);
+                self.walk(body);
+            }
+            StmtKind::Block(body) => self.walk(body),
+            StmtKind::Switch(expr, cases, default) => {
+                self.visit_expr(expr);
+                switch_bodies(cases, default).for_each(|body| self.walk(body));
+            }
+            StmtKind::Label(name) => {
+                self.labels.insert(name.clone());
+            }
+            StmtKind::Return | StmtKind::Halt | StmtKind::Nop | StmtKind::Dump => (),
+        }
+    }
+
+    fn visit_expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Load(e, _) | ExprKind::Unary(_, e) => self.visit_expr(e),
+            ExprKind::Assign(var, e) => {
+                self.writes.insert(var.lexeme.clone());
+                self.visit_expr(e);
+            }
+            ExprKind::Binary(lhs, _, rhs) => {
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            }
+            ExprKind::Conditional(cond, iftrue, iffalse) => {
+                self.visit_expr(cond);
+                self.visit_expr(iftrue);
+                self.visit_expr(iffalse);
+            }
+            ExprKind::Call(_, args) | ExprKind::Array(args) => {
+                args.iter().for_each(|arg| self.visit_expr(arg))
+            }
+            ExprKind::Index(array, index) => {
+                self.visit_expr(array);
+                self.visit_expr(index);
+            }
+            ExprKind::Var(var) => {
+                self.reads.insert(var.lexeme.clone());
+                self.uses.push(var.clone());
+            }
+            ExprKind::GetInput(..)
+            | ExprKind::Val(_)
+            | ExprKind::Float(_)
+            | ExprKind::Str(_)
+            | ExprKind::Register(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn analyze(src: &str) -> Vec<AnalysisError> {
+        undefined_variables(&Parser::new(Scanner::new(src)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn unassigned_variable() {
+        assert_eq!(
+            analyze("x := 1\ny := x + z"),
+            vec![AnalysisError::UndefinedVariable {
+                name: "z".into(),
+                line: 2,
+                column: 10
+            }]
+        );
+    }
+
+    #[test]
+    fn errors_sorted_by_position() {
+        let errors = analyze("x := y\nz := w + v");
+        let positions: Vec<_> = errors.iter().map(AnalysisError::position).collect();
+        assert_eq!(positions, vec![(1, 6), (2, 6), (2, 10)]);
+
+        let mut errors = [
+            AnalysisError::BadJumpTarget {
+                line: 3,
+                column: 1,
+                target: 9,
+            },
+            AnalysisError::UndefinedVariable {
+                name: "x".into(),
+                line: 1,
+                column: 5,
+            },
+        ];
+        errors.sort();
+        assert_eq!(errors[0].position(), (1, 5));
+    }
+
+    #[test]
+    fn assigned_variable() {
+        assert_eq!(analyze("x := 1 y := x + 1 print y"), vec![]);
+        assert_eq!(analyze("x := y := 1 print x + y"), vec![]);
+        assert_eq!(analyze("x := MAX_U32 - PI"), vec![]);
+    }
+
+    fn unreachable(src: &str) -> Vec<AnalysisWarning> {
+        unreachable_statements(&Parser::new(Scanner::new(src)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn unreachable_after_goto() {
+        assert_eq!(
+            unreachable("goto 3\nx := 1\nprint 2\nhalt"),
+            vec![AnalysisWarning { line: 2, column: 1 }]
+        );
+        assert_eq!(
+            unreachable("halt\nprint 1\nwhile 1 do\n  return\n  print 2\nend"),
+            vec![
+                AnalysisWarning { line: 2, column: 1 },
+                AnalysisWarning { line: 5, column: 3 }
+            ]
+        );
+    }
+
+    #[test]
+    fn reachable_after_goto() {
+        assert_eq!(unreachable("goto done\nlabel done:\nprint 1"), vec![]);
+        assert_eq!(
+            unreachable("if 1 then goto 2 else goto 2\ngoto 0\nprint 1"),
+            vec![]
+        );
+        assert_eq!(unreachable("x := 1\nprint x"), vec![]);
+    }
+
+    #[test]
+    fn labels_and_nested_assignments() {
+        assert_eq!(analyze("goto end_ label end_: { x := 1 } print x"), vec![]);
+    }
+
+    fn bad_jumps(src: &str) -> Vec<AnalysisError> {
+        bad_jump_targets(&Parser::new(Scanner::new(src)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn jump_past_the_end() {
+        assert_eq!(
+            bad_jumps("x := 1\ngoto 50\nprint x"),
+            vec![AnalysisError::BadJumpTarget {
+                line: 2,
+                column: 6,
+                target: 50
+            }]
+        );
+        assert_eq!(
+            bad_jumps("while 1 do\n  if x then goto 0 else goto 9\nend"),
+            vec![AnalysisError::BadJumpTarget {
+                line: 2,
+                column: 30,
+                target: 9
+            }]
+        );
+    }
+
+    #[test]
+    fn jump_within_the_program() {
+        assert_eq!(bad_jumps("x := 1\ngoto 2"), vec![]);
+        assert_eq!(bad_jumps("call 1 + 50\ngoto done\nlabel done:"), vec![]);
+    }
+
+    fn names(names: &[&str]) -> Set<String> {
+        names.iter().map(|&name| name.to_owned()).collect()
+    }
+
+    #[test]
+    fn variables_read_and_written() {
+        let usage =
+            variable_usage(&Parser::new(Scanner::new("x := 1; y := x + 2")).collect::<Vec<_>>());
+        assert_eq!(usage.writes, names(&["x", "y"]));
+        assert_eq!(usage.reads, names(&["x"]));
+
+        let usage = variable_usage(
+            &Parser::new(Scanner::new("while i do const n := i + j end print m"))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(usage.writes, names(&["n"]));
+        assert_eq!(usage.reads, names(&["i", "j", "m"]));
+
+        let usage = variable_usage(
+            &Parser::new(Scanner::new("label done:\ngoto done")).collect::<Vec<_>>(),
+        );
+        assert_eq!(usage.labels, names(&["done"]));
+        assert_eq!(usage.reads, names(&["done"]));
+        assert!(usage.writes.is_empty());
+    }
+}
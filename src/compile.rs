@@ -0,0 +1,413 @@
+use crate::interpreter::{comparison, labels};
+use crate::syntax::{Expr, ExprKind, Stmt, StmtKind};
+use crate::tokens::{Token, TokenType};
+use crate::value::{Value, WordWidth};
+use std::collections::HashMap as Map;
+
+/// An instruction of the [`Vm`](../vm/struct.Vm.html), which works on a stack of values.
+///
+/// Addresses are indices into the instructions, while jump targets popped from the stack
+/// are indices of top-level statements, as in the tree-walking interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// Begin the top-level statement with this index, counting a step.
+    Statement(usize),
+    /// Count a step, for a nested statement or an iteration of a loop.
+    Step,
+    /// Push a value.
+    Push(Value),
+    /// Push an integer literal from this line, failing if it does not fit the word width.
+    Literal(u32, usize),
+    /// Discard the top value.
+    Pop,
+    /// Pop the value of a top-level statement into the results, see [`Stmt::produces_value`].
+    Result,
+    /// Fail if the variable is a constant.
+    CheckConst(String),
+    /// Assign the top value to the variable, making it a constant if set.
+    Assign(String, bool),
+    /// Push the value of the variable.
+    Get(String),
+    /// Fail, as a named register can only be stored or loaded.
+    Register(String),
+    /// Push the value of the variable as a jump target, failing with an undefined label.
+    Target(String),
+    /// Fail unless the top value is an integer.
+    AsInt,
+    /// Pop a value and a register, store the value and push the stored value.
+    Store(WordWidth),
+    /// Pop a register and push its value.
+    Load(WordWidth),
+    /// Pop a value, store it in the named register and push the stored value.
+    StoreNamed(String, WordWidth),
+    /// Push the value of the named register.
+    LoadNamed(String, WordWidth),
+    /// Pop two operands and push the result of the operator.
+    Binary(Token),
+    /// Pop an operand and push the result of the operator.
+    Unary(Token),
+    /// Pop a value and push `1` if it is truthy, else `0`.
+    Truthy,
+    /// Pop the arguments and push the result of the built-in function.
+    Builtin(Token, usize),
+    /// Pop this many integers and push them as an array.
+    Array(usize),
+    /// Pop an index and an array and push the element.
+    Index,
+    /// Push a value read from the input channel.
+    Input(String, Option<String>),
+    /// Write the top value to the output.
+    Print,
+    /// Write the variables and registers to the output.
+    Dump,
+    /// Fail unless the top value is `1`, reporting the line of the `assert`.
+    Assert(usize),
+    /// Pop two operands and compare them, failing with both unless the result is `1`.
+    AssertCompare(usize, Token),
+    /// Continue at the address.
+    Jump(usize),
+    /// Pop a value and continue at the address unless it is truthy.
+    JumpUnless(usize),
+    /// Pop a condition and continue at the address if it is `0`,
+    /// failing unless it is `0` or `1`.
+    Branch(usize),
+    /// Pop the top integer if it equals the value, else continue at the address.
+    Case(u32, usize),
+    /// Begin a nested scope.
+    EnterScope,
+    /// End the innermost nested scope.
+    ExitScope,
+    /// Pop a statement index and continue there.
+    Goto,
+    /// Like `Goto`, but first remember the statement index `resume` for `Return`.
+    Call { resume: usize },
+    /// Continue at the statement remembered by the latest `Call`.
+    Return,
+    /// Stop the program.
+    Halt,
+}
+
+/// Compile a program into instructions for the [`Vm`](../vm/struct.Vm.html).
+pub fn compile(stmts: &[Stmt]) -> Vec<Op> {
+    let mut compiler = Compiler {
+        ops: Vec::new(),
+        labels: labels(stmts, 0).collect(),
+    };
+    for (i, stmt) in stmts.iter().enumerate() {
+        compiler.ops.push(Op::Statement(i));
+        if compiler.stmt(stmt, i + 1) {
+            compiler.ops.push(if stmt.produces_value() {
+                Op::Result
+            } else {
+                Op::Pop
+            });
+        }
+    }
+    compiler.ops
+}
+
+/// Emits the instructions of a program.
+struct Compiler {
+    ops: Vec<Op>,
+    labels: Map<String, usize>,
+}
+
+impl Compiler {
+    /// Emit a statement, within the top-level statement preceding `resume`.
+    /// Returns true if the statement leaves its value on the stack,
+    /// and false if it always jumps.
+    fn stmt(&mut self, stmt: &Stmt, resume: usize) -> bool {
+        match &stmt.kind {
+            StmtKind::Assignment(var, e) | StmtKind::ConstAssignment(var, e) => {
+                self.ops.push(Op::CheckConst(var.lexeme.clone()));
+                self.expr(e);
+                let constant = matches!(stmt.kind, StmtKind::ConstAssignment(..));
+                self.ops.push(Op::Assign(var.lexeme.clone(), constant));
+            }
+            StmtKind::Store(reg, val, width) => match &reg.kind {
+                ExprKind::Register(name) => {
+                    self.expr(val);
+                    self.ops.push(Op::StoreNamed(name.clone(), *width));
+                }
+                _ => {
+                    self.expr(reg);
+                    self.ops.push(Op::AsInt);
+                    self.expr(val);
+                    self.ops.push(Op::Store(*width));
+                }
+            },
+            StmtKind::Goto(target) => {
+                self.target(target);
+                self.ops.push(Op::Goto);
+                return false;
+            }
+            StmtKind::Call(target) => {
+                self.target(target);
+                self.ops.push(Op::Call { resume });
+                return false;
+            }
+            StmtKind::Return => {
+                self.ops.push(Op::Return);
+                return false;
+            }
+            StmtKind::Halt => {
+                self.ops.push(Op::Halt);
+                return false;
+            }
+            StmtKind::Assert(keyword, e) => match comparison(e) {
+                Some((lhs, op, rhs)) => {
+                    self.expr(lhs);
+                    self.expr(rhs);
+                    self.ops.push(Op::AssertCompare(keyword.line, op.clone()));
+                }
+                None => {
+                    self.expr(e);
+                    self.ops.push(Op::Assert(keyword.line));
+                }
+            },
+            StmtKind::IfThenElse(cond, iftrue, iffalse) => {
+                self.expr(cond);
+                let branch = self.emit(Op::Branch(0));
+                let then = self.branch(iftrue, resume);
+                let end = then.then(|| self.emit(Op::Jump(0)));
+                self.patch(branch);
+                let otherwise = self.branch(iffalse, resume);
+                end.into_iter().for_each(|end| self.patch(end));
+                if !then && !otherwise {
+                    return false;
+                }
+                self.ops.push(Op::Push(Value::Int(0)));
+            }
+            StmtKind::Label(_) | StmtKind::Nop => self.ops.push(Op::Push(Value::Int(0))),
+            StmtKind::Expression(e) => self.expr(e),
+            StmtKind::Dump => {
+                self.ops.push(Op::Dump);
+                self.ops.push(Op::Push(Value::Int(0)));
+            }
+            StmtKind::Print(e) => {
+                self.expr(e);
+                self.ops.push(Op::Print);
+            }
+            StmtKind::While(cond, body) => {
+                let start = self.ops.len();
+                self.ops.push(Op::Step);
+                self.expr(cond);
+                let exit = self.emit(Op::JumpUnless(0));
+                self.body(body, resume);
+                self.ops.push(Op::Jump(start));
+                self.patch(exit);
+                self.ops.push(Op::Push(Value::Int(0)));
+            }
+            StmtKind::Block(body) => {
+                self.ops.push(Op::EnterScope);
+                self.body(body, resume);
+                self.ops.push(Op::ExitScope);
+                self.ops.push(Op::Push(Value::Int(0)));
+            }
+            StmtKind::Switch(e, cases, default) => {
+                self.expr(e);
+                self.ops.push(Op::AsInt);
+                let mut ends = Vec::new();
+                for (value, body) in cases {
+                    let next = self.emit(Op::Case(*value, 0));
+                    self.body(body, resume);
+                    ends.push(self.emit(Op::Jump(0)));
+                    self.patch(next);
+                }
+                self.ops.push(Op::Pop);
+                if let Some(body) = default {
+                    self.body(body, resume);
+                }
+                for end in ends {
+                    self.patch(end);
+                }
+                self.ops.push(Op::Push(Value::Int(0)));
+            }
+        }
+        true
+    }
+
+    /// Emit the statements of a nested body, discarding their values.
+    fn body(&mut self, body: &[Stmt], resume: usize) {
+        for stmt in body {
+            self.ops.push(Op::Step);
+            if self.stmt(stmt, resume) {
+                self.ops.push(Op::Pop);
+            }
+        }
+    }
+
+    /// Emit a branch of an `if`, discarding its value.
+    /// Returns false if it always jumps.
+    fn branch(&mut self, stmt: &Stmt, resume: usize) -> bool {
+        let continues = self.stmt(stmt, resume);
+        if continues {
+            self.ops.push(Op::Pop);
+        }
+        continues
+    }
+
+    /// Emit the target of a jump, either a label or an expression.
+    fn target(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Var(name) => match self.labels.get(&name.lexeme) {
+                Some(&position) => self.ops.push(Op::Push(Value::Int(position as u32))),
+                None => self.ops.push(Op::Target(name.lexeme.clone())),
+            },
+            _ => self.expr(e),
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Load(reg, width) => match &reg.kind {
+                ExprKind::Register(name) => self.ops.push(Op::LoadNamed(name.clone(), *width)),
+                _ => {
+                    self.expr(reg);
+                    self.ops.push(Op::Load(*width));
+                }
+            },
+            ExprKind::Binary(lhs, op, rhs) if op.token_type == TokenType::And => {
+                self.expr(lhs);
+                let short = self.emit(Op::JumpUnless(0));
+                self.expr(rhs);
+                self.ops.push(Op::Truthy);
+                let end = self.emit(Op::Jump(0));
+                self.patch(short);
+                self.ops.push(Op::Push(Value::Int(0)));
+                self.patch(end);
+            }
+            ExprKind::Binary(lhs, op, rhs) if op.token_type == TokenType::Or => {
+                self.expr(lhs);
+                let long = self.emit(Op::JumpUnless(0));
+                self.ops.push(Op::Push(Value::Int(1)));
+                let end = self.emit(Op::Jump(0));
+                self.patch(long);
+                self.expr(rhs);
+                self.ops.push(Op::Truthy);
+                self.patch(end);
+            }
+            ExprKind::Binary(lhs, op, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.ops.push(Op::Binary(op.clone()));
+            }
+            ExprKind::Unary(op, rhs) => {
+                self.expr(rhs);
+                self.ops.push(Op::Unary(op.clone()));
+            }
+            ExprKind::Var(var) => self.ops.push(Op::Get(var.lexeme.clone())),
+            ExprKind::GetInput(channel, key) => {
+                self.ops.push(Op::Input(channel.clone(), key.clone()))
+            }
+            ExprKind::Val(val) => self.ops.push(Op::Literal(*val, e.span.start_line)),
+            ExprKind::Float(val) => self.ops.push(Op::Push(Value::Float(*val))),
+            ExprKind::Str(val) => self.ops.push(Op::Push(Value::Str(val.clone()))),
+            ExprKind::Register(name) => self.ops.push(Op::Register(name.clone())),
+            ExprKind::Assign(var, e) => {
+                self.ops.push(Op::CheckConst(var.lexeme.clone()));
+                self.expr(e);
+                self.ops.push(Op::Assign(var.lexeme.clone(), false));
+            }
+            ExprKind::Conditional(cond, iftrue, iffalse) => {
+                self.expr(cond);
+                let otherwise = self.emit(Op::JumpUnless(0));
+                self.expr(iftrue);
+                let end = self.emit(Op::Jump(0));
+                self.patch(otherwise);
+                self.expr(iffalse);
+                self.patch(end);
+            }
+            ExprKind::Call(name, args) => {
+                args.iter().for_each(|arg| self.expr(arg));
+                self.ops.push(Op::Builtin(name.clone(), args.len()));
+            }
+            ExprKind::Array(items) => {
+                items.iter().for_each(|item| self.expr(item));
+                self.ops.push(Op::Array(items.len()));
+            }
+            ExprKind::Index(array, index) => {
+                self.expr(array);
+                self.expr(index);
+                self.ops.push(Op::Index);
+            }
+        }
+    }
+
+    /// Emit a jump whose address is filled in later by `patch`, returning its position.
+    fn emit(&mut self, op: Op) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    /// Make the jump at `position` continue at the next instruction to be emitted.
+    fn patch(&mut self, position: usize) {
+        let here = self.ops.len();
+        match &mut self.ops[position] {
+            Op::Jump(address)
+            | Op::JumpUnless(address)
+            | Op::Branch(address)
+            | Op::Case(_, address) => *address = here,
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn compile_str(src: &str) -> Vec<Op> {
+        compile(&Parser::new(Scanner::new(src)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn compile_assignment() {
+        assert_eq!(
+            compile_str("x := 1"),
+            vec![
+                Op::Statement(0),
+                Op::CheckConst("x".into()),
+                Op::Literal(1, 1),
+                Op::Assign("x".into(), false),
+                Op::Result,
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_while() {
+        assert_eq!(
+            compile_str("while x do print x end"),
+            vec![
+                Op::Statement(0),
+                Op::Step,
+                Op::Get("x".into()),
+                Op::JumpUnless(9),
+                Op::Step,
+                Op::Get("x".into()),
+                Op::Print,
+                Op::Pop,
+                Op::Jump(1),
+                Op::Push(Value::Int(0)),
+                Op::Pop,
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_labels() {
+        assert_eq!(
+            compile_str("label top: goto top"),
+            vec![
+                Op::Statement(0),
+                Op::Push(Value::Int(0)),
+                Op::Pop,
+                Op::Statement(1),
+                Op::Push(Value::Int(0)),
+                Op::Goto,
+            ]
+        );
+    }
+}
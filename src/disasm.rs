@@ -0,0 +1,35 @@
+use crate::syntax::Stmt;
+
+/// Render the statements as source one per line, each prefixed by its index,
+/// so the targets of `goto` can be read off directly, e.g. `0001  goto 0`.
+///
+/// The further lines of a nested body are indented past the indices,
+/// as its statements cannot be jumped to.
+pub fn disassemble(stmts: &[Stmt]) -> String {
+    stmts
+        .iter()
+        .enumerate()
+        .map(|(i, stmt)| format!("{:04}  {}\n", i, stmt.to_string().replace('\n', "\n      ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    #[test]
+    fn disassemble_numbers_statements() {
+        let stmts: Vec<_> = Parser::new(Scanner::new("x := 1 goto 0")).collect();
+        assert_eq!(disassemble(&stmts), "0000  x := 1\n0001  goto 0\n");
+    }
+
+    #[test]
+    fn disassemble_nested_bodies() {
+        let stmts: Vec<_> = Parser::new(Scanner::new("while i < 10 do i := i + 1 end")).collect();
+        assert_eq!(
+            disassemble(&stmts),
+            "0000  while i < 10 do\n        i := i + 1\n      end\n"
+        );
+    }
+}
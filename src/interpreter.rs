@@ -1,113 +1,2441 @@
-#![allow(dead_code)]
-
-use crate::syntax::{Expr, Stmt};
+use crate::memory::Memory;
+use crate::syntax::{Expr, ExprKind, Stmt, StmtKind};
+use crate::tokens::{Token, TokenType};
+use crate::value::{OutputFormat, Value, WordWidth};
+use crate::visitor::Visitor;
+use std::collections::BTreeMap;
 use std::collections::HashMap as Map;
+use std::collections::HashSet as Set;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
+use thiserror::Error;
 use tracing::event;
 use tracing::Level;
 
+/// The seed of `rand()` unless one is given with `Interpreter::with_seed`.
+const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+/// An enum used for reporting errors during execution.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// The right-hand side of a division was zero.
+    #[error("[line {line}] division by zero")]
+    DivisionByZero { line: usize },
+    /// An arithmetic operation overflowed under `OverflowMode::Checked`.
+    #[error("[line {line}] arithmetic overflow")]
+    Overflow { line: usize },
+    /// A variable was read before being assigned.
+    #[error("undefined variable '{0}'")]
+    UndefinedVariable(String),
+    /// A register was loaded before being stored.
+    #[error("undefined register '{0}'")]
+    UndefinedRegister(u32),
+    /// A named register was loaded before being stored.
+    #[error("undefined register '@{0}'")]
+    UndefinedNamedRegister(String),
+    /// A named register was used as a value, rather than stored or loaded.
+    #[error("register '@{0}' can only be stored or loaded")]
+    NamedRegisterValue(String),
+    /// A goto named a label which does not exist.
+    #[error("undefined label '{0}'")]
+    UndefinedLabel(String),
+    /// Writing to the output failed.
+    #[error("output error: {0}")]
+    Output(String),
+    /// Reading from the input failed.
+    #[error("input error: {0}")]
+    Input(String),
+    /// The input ran out before a value could be read.
+    #[error("input exhausted")]
+    InputExhausted,
+    /// The input could not be read as a value.
+    #[error("invalid input '{0}'")]
+    InvalidInput(String),
+    /// `get_input(env, ..)` named an environment variable which is not set.
+    #[error("undefined environment variable '{0}'")]
+    UndefinedEnvVar(String),
+    /// `get_input` named a channel which does not exist,
+    /// which only a hand-built syntax tree can contain.
+    #[error("unknown input channel '{0}'")]
+    UnknownChannel(String),
+    /// The program executed more statements than allowed.
+    #[error("step limit of {0} exceeded")]
+    StepLimitExceeded(usize),
+    /// The program printed more values than allowed.
+    #[error("output limit of {0} exceeded")]
+    OutputLimitExceeded(usize),
+    /// A jump targeted a statement outside of the program.
+    #[error("invalid jump target {0}")]
+    InvalidJumpTarget(u32),
+    /// An address beyond the end of fixed-size memory was accessed.
+    #[error("address {0} out of bounds")]
+    OutOfBounds(u32),
+    /// An integer was required, e.g. as an address or jump target.
+    #[error("expected an integer, found {0}")]
+    ExpectedInt(Value),
+    /// An array was required, i.e. as the operand of an index.
+    #[error("expected an array, found {0}")]
+    ExpectedArray(Value),
+    /// An index beyond the end of an array.
+    #[error("index {index} out of bounds for an array of length {len}")]
+    IndexOutOfBounds { index: u32, len: usize },
+    /// A number was required, e.g. as an operand of arithmetic.
+    #[error("expected a number, found {0}")]
+    ExpectedNumber(Value),
+    /// The operator cannot be applied to the operand types.
+    #[error("[line {line}] invalid operands for '{op}'")]
+    InvalidOperands { line: usize, op: String },
+    /// An expression used a token which is not an operator of its kind,
+    /// which only a hand-built syntax tree can contain.
+    #[error("[line {line}] invalid operator '{op}'")]
+    InvalidOperator { line: usize, op: String },
+    /// A built-in function was unknown or given the wrong number of arguments,
+    /// which only a hand-built syntax tree can contain.
+    #[error("[line {line}] invalid call to '{name}'")]
+    InvalidCall { line: usize, name: String },
+    /// A variable declared with `const` was assigned again.
+    #[error("cannot assign to constant '{0}'")]
+    ReassignConst(String),
+    /// A `return` was executed without a matching `call`.
+    #[error("return without call")]
+    ReturnWithoutCall,
+    /// The condition of an `if` was neither `0` nor `1`.
+    #[error("condition must be 0 or 1, found {0}")]
+    NonBooleanCondition(Value),
+    /// An `assert` whose condition was not `1`.
+    #[error("assertion failed at line {line} (evaluated to {value})")]
+    AssertionFailed { line: usize, value: Value },
+    /// An `assert` of a comparison which did not hold, with the values compared.
+    #[error("assertion failed at line {line} ({lhs} {op} {rhs} is false)")]
+    ComparisonFailed {
+        line: usize,
+        op: String,
+        lhs: Value,
+        rhs: Value,
+    },
+    /// An integer literal was larger than the word width allows.
+    #[error("[line {line}] literal {value} does not fit in the word width")]
+    LiteralTooWide { line: usize, value: u32 },
+}
+
+/// How arithmetic behaves when a result does not fit in a `u32`.
+///
+/// Values are unsigned, so negation is also subject to the overflow mode:
+/// `Wrapping` gives the two's complement (`-1` is `u32::MAX`),
+/// `Checked` fails for anything but `-0`, and `Saturating` always gives `0`.
+/// A subtraction which wraps around under `Wrapping` is also logged as a warning,
+/// as `x - y` is easily mistaken for a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OverflowMode {
+    /// Wrap around at the boundary of the type.
+    #[default]
+    Wrapping,
+    /// Abort execution with a `RuntimeError::Overflow`.
+    Checked,
+    /// Clamp the result to `0` or `u32::MAX`.
+    Saturating,
+}
+
+/// Whether programs can use the predefined constants, see `builtin_constant`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BuiltinConstants {
+    /// The names are ordinary variables.
+    Off,
+    /// The constants can be used, and assigning a variable of the same name hides them.
+    #[default]
+    Shadowable,
+    /// The constants can be used, and assigning them is an error, as for `const`.
+    Protected,
+}
+
+/// The value of the predefined constant `name`, if there is one.
+pub fn builtin_constant(name: &str) -> Option<Value> {
+    let value = match name {
+        "MIN_U32" => Value::Int(0),
+        "MAX_U8" => Value::Int(u8::MAX.into()),
+        "MAX_U16" => Value::Int(u16::MAX.into()),
+        "MAX_U32" => Value::Int(u32::MAX),
+        "PI" => Value::Float(std::f64::consts::PI),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// The state of an interpreter after running a program to completion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpreterOutcome {
+    /// The final value of every variable in scope, by name.
+    pub vars: BTreeMap<String, Value>,
+    /// The final value of every stored register, by address.
+    pub registers: BTreeMap<u32, u32>,
+    /// The final value of every stored named register, by name.
+    pub named_registers: BTreeMap<String, u32>,
+    /// The value of each executed statement which produces one, as returned by `visit`.
+    pub results: Vec<Value>,
+    /// Each line written by `print` and `dump`, as it was also written to the writer.
+    pub output: Vec<String>,
+}
+
 pub struct Interpreter {
-    statements: Vec<Stmt>,    // Sigma
-    registers: Map<u32, u32>, // µ
-    vars: Map<String, u32>,   // Delta
-    program_counter: usize,   // pc
+    statements: Vec<Rc<Stmt>>, // Sigma, shared so a step need not copy its statement
+    registers: Memory,         // µ
+    vars: Vec<Map<String, Value>>, // Delta, innermost scope last
+    consts: Set<String>,
+    program_counter: usize, // pc
+    labels: Map<String, usize>,
+    named_registers: Map<String, u32>,
+    overflow_mode: OverflowMode,
+    word_width: WordWidth,
+    writer: Box<dyn Write>,
+    reader: Box<dyn BufRead>,
+    inputs: Option<VecDeque<u32>>,
+    args: VecDeque<String>,
+    env: Option<Map<String, String>>,
+    steps: usize,
+    step_limit: Option<usize>,
+    outputs: usize,
+    output: Vec<String>,
+    output_limit: Option<usize>,
+    output_format: OutputFormat,
+    jumped: bool,
+    call_stack: Vec<usize>,
+    trace: Option<Box<dyn Write>>,
+    builtins: BuiltinConstants,
+    seed: u64,
+    rng: u64,
 }
 
 impl Interpreter {
-    pub fn visit(mut self) -> Vec<u32> {
+    /// Run the program, returning the value of each executed top-level statement
+    /// which produces one, see [`Stmt::produces_value`]. Jumps, stores and other
+    /// statements which only have an effect add nothing.
+    pub fn visit(mut self) -> Result<Vec<Value>, RuntimeError> {
+        self.run()
+    }
+
+    /// Run the program, returning the final variables and registers along with the results.
+    pub fn run_to_completion(mut self) -> Result<InterpreterOutcome, RuntimeError> {
+        let results = self.run()?;
+        Ok(self.outcome(results))
+    }
+
+    /// The current variables and registers, along with `results`.
+    pub(crate) fn outcome(&self, results: Vec<Value>) -> InterpreterOutcome {
+        InterpreterOutcome {
+            vars: self
+                .vars()
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value.clone()))
+                .collect(),
+            registers: self.registers.entries().into_iter().collect(),
+            named_registers: self
+                .named_registers()
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+            results,
+            output: self.output.clone(),
+        }
+    }
+
+    /// Append `statements` to the program and run only them,
+    /// keeping the variables, memory and labels of earlier runs.
+    pub fn execute(&mut self, statements: Vec<Stmt>) -> Result<Vec<Value>, RuntimeError> {
+        let offset = self.statements.len();
+        self.labels.extend(labels(&statements, offset));
+        self.statements.extend(statements.into_iter().map(Rc::new));
+        self.program_counter = offset;
+        self.run()
+    }
+
+    /// The variables currently in scope, by name.
+    pub fn vars(&self) -> BTreeMap<&str, &Value> {
+        self.vars
+            .iter()
+            .flat_map(|scope| scope.iter())
+            .map(|(name, value)| (name.as_str(), value))
+            .collect()
+    }
+
+    /// The memory addressed by `store` and `load`.
+    pub fn registers(&self) -> &Memory {
+        &self.registers
+    }
+
+    /// The stored named registers, by name.
+    pub fn named_registers(&self) -> BTreeMap<&str, u32> {
+        self.named_registers
+            .iter()
+            .map(|(name, &value)| (name.as_str(), value))
+            .collect()
+    }
+
+    /// Return to the start of the program, forgetting the variables, constants, registers
+    /// and steps of earlier runs, so that `run` executes it again.
+    /// The statements and the options the interpreter was created with are kept.
+    pub fn reset(&mut self) {
+        self.vars = vec![Map::new()];
+        self.consts.clear();
+        self.registers.clear();
+        self.named_registers.clear();
+        self.program_counter = 0;
+        self.call_stack.clear();
+        self.jumped = false;
+        self.steps = 0;
+        self.outputs = 0;
+        self.output.clear();
+        self.rng = self.seed;
+    }
+
+    /// Execute statements from the program counter until the end of the program,
+    /// returning the values like `visit`. Unlike `visit`, the interpreter is kept,
+    /// so it can be inspected or `reset` and run again.
+    pub fn run(&mut self) -> Result<Vec<Value>, RuntimeError> {
         let mut res = Vec::new();
         while self.program_counter < self.statements.len() {
             event!(Level::INFO, "Statement: {}", &self.program_counter);
-            let statement = { self.statements[self.program_counter].clone() };
-            res.push(self.visit_stmt(&statement));
+            self.step()?;
+            let statement = Rc::clone(&self.statements[self.program_counter]);
+            self.program_counter += 1;
+            self.jumped = false;
+            let value = self.visit_traced(&statement)?;
+            if statement.produces_value() {
+                res.push(value);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Count an executed step, failing once the step limit is reached.
+    pub(crate) fn step(&mut self) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(RuntimeError::StepLimitExceeded(limit));
+            }
         }
-        res
+        self.steps += 1;
+        Ok(())
     }
 
-    pub fn new(statements: impl Iterator<Item = Stmt>) -> Self {
+    /// Count the step of beginning the top-level statement at `index`,
+    /// for a caller which keeps its own position, such as the VM.
+    pub(crate) fn begin(&mut self, index: usize) -> Result<(), RuntimeError> {
+        self.program_counter = index + 1;
+        self.step()
+    }
+
+    /// Execute a statement, first writing it to the trace if there is one.
+    /// Nested statements are traced with the position of the outermost statement.
+    fn visit_traced(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let pc = self.program_counter - 1;
+        self.write_trace(|| format!("pc={}: {}", pc, stmt))?;
+        let res = self.visit_stmt(stmt)?;
+        if let StmtKind::Goto(_) | StmtKind::IfThenElse(..) | StmtKind::Call(_) | StmtKind::Return =
+            stmt.kind
+        {
+            if self.jumped {
+                let target = self.program_counter;
+                self.write_trace(|| format!("pc={}: -> {}", pc, target))?;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Write a line to the trace, if tracing is enabled.
+    fn write_trace(&mut self, line: impl FnOnce() -> String) -> Result<(), RuntimeError> {
+        match &mut self.trace {
+            Some(trace) => {
+                writeln!(trace, "{}", line()).map_err(|err| RuntimeError::Output(err.to_string()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Execute the statements of a nested body.
+    /// A jump abandons the rest of the body, and returns false.
+    fn visit_body(&mut self, body: &[Stmt]) -> Result<bool, RuntimeError> {
+        for stmt in body {
+            self.step()?;
+            self.visit_traced(stmt)?;
+            if self.jumped {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn new(statements: Vec<Stmt>) -> Self {
+        let labels = labels(&statements, 0).collect();
         Self {
-            statements: statements.collect(),
-            registers: Map::new(),
-            vars: Map::new(),
+            statements: statements.into_iter().map(Rc::new).collect(),
+            registers: Memory::default(),
+            vars: vec![Map::new()],
+            consts: Set::new(),
             program_counter: 0,
+            labels,
+            named_registers: Map::new(),
+            overflow_mode: OverflowMode::default(),
+            word_width: WordWidth::default(),
+            writer: Box::new(io::stdout()),
+            reader: Box::new(BufReader::new(io::stdin())),
+            inputs: None,
+            args: VecDeque::new(),
+            env: None,
+            steps: 0,
+            step_limit: None,
+            outputs: 0,
+            output: Vec::new(),
+            output_limit: None,
+            output_format: OutputFormat::default(),
+            jumped: false,
+            call_stack: Vec::new(),
+            trace: None,
+            builtins: BuiltinConstants::default(),
+            seed: DEFAULT_SEED,
+            rng: DEFAULT_SEED,
         }
     }
+
+    /// Create an interpreter whose `store` and `load` address a fixed-size memory of `size` words.
+    pub fn with_memory_size(statements: Vec<Stmt>, size: usize) -> Self {
+        Self::new(statements).memory(Memory::linear(size))
+    }
+
+    /// Create an interpreter whose `store` and `load` address a memory of `size` words,
+    /// taking addresses modulo `size`.
+    pub fn with_wrapping_memory(statements: Vec<Stmt>, size: usize) -> Self {
+        Self::new(statements).memory(Memory::wrapping(size))
+    }
+
+    /// Create an interpreter which fails after executing `limit` statements.
+    pub fn with_step_limit(statements: Vec<Stmt>, limit: usize) -> Self {
+        Self::new(statements).step_limit(limit)
+    }
+
+    /// Create an interpreter which reads `get_input` values from `reader` instead of `stdin`.
+    pub fn with_input(statements: Vec<Stmt>, reader: impl BufRead + 'static) -> Self {
+        Self::new(statements).reader(reader)
+    }
+
+    /// Create an interpreter whose `get_input` takes values from the front of `inputs`
+    /// instead of reading `stdin`.
+    pub fn with_inputs(statements: Vec<Stmt>, inputs: Vec<u32>) -> Self {
+        Self::new(statements).inputs(inputs)
+    }
+
+    /// Create an interpreter which fails when a `print` would exceed `limit` printed values.
+    pub fn with_output_limit(statements: Vec<Stmt>, limit: usize) -> Self {
+        Self::new(statements).output_limit(limit)
+    }
+
+    /// Create an interpreter whose `get_input(env, ..)` reads from `env`
+    /// instead of the environment of the process.
+    pub fn with_env(statements: Vec<Stmt>, env: Map<String, String>) -> Self {
+        Self::new(statements).env(env)
+    }
+
+    /// Create an interpreter which prints to `writer` instead of `stdout`.
+    pub fn with_writer(statements: Vec<Stmt>, writer: impl Write + 'static) -> Self {
+        Self::new(statements).writer(writer)
+    }
+
+    /// Create an interpreter which writes each statement to `trace` before executing it.
+    pub fn with_trace(statements: Vec<Stmt>, trace: impl Write + 'static) -> Self {
+        Self::new(statements).trace(trace)
+    }
+
+    /// Create an interpreter whose integers are `width` bits wide.
+    /// Results are kept within the width according to the overflow mode.
+    /// An integer literal wider than `width` fails when it is evaluated.
+    pub fn with_word_width(statements: Vec<Stmt>, width: WordWidth) -> Self {
+        Self::new(statements).word_width(width)
+    }
+
+    /// Create an interpreter whose programs can use the predefined constants as given.
+    pub fn with_builtins(statements: Vec<Stmt>, builtins: BuiltinConstants) -> Self {
+        Self::new(statements).builtins(builtins)
+    }
+
+    /// Create an interpreter whose `rand()` gives the sequence determined by `seed`.
+    pub fn with_seed(statements: Vec<Stmt>, seed: u64) -> Self {
+        Self::new(statements).seed(seed)
+    }
+
+    /// Create an interpreter which handles arithmetic overflow according to `mode`.
+    pub fn with_overflow_mode(statements: Vec<Stmt>, mode: OverflowMode) -> Self {
+        Self::new(statements).overflow_mode(mode)
+    }
+
+    /// Use `memory` for `store` and `load`, e.g. `Memory::wrapping(size)`.
+    /// Unlike the `with_*` constructors, the builder methods can be chained.
+    pub fn memory(mut self, memory: Memory) -> Self {
+        self.registers = memory;
+        self
+    }
+
+    /// Fail after executing `limit` statements.
+    pub fn step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// Read `get_input` values from `reader` instead of `stdin`.
+    pub fn reader(mut self, reader: impl BufRead + 'static) -> Self {
+        self.reader = Box::new(reader);
+        self
+    }
+
+    /// Take `get_input` values from the front of `inputs` instead of reading `stdin`.
+    pub fn inputs(mut self, inputs: Vec<u32>) -> Self {
+        self.inputs = Some(inputs.into());
+        self
+    }
+
+    /// Fail when a `print` would exceed `limit` printed values.
+    pub fn output_limit(mut self, limit: usize) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Read `get_input(env, ..)` from `env` instead of the environment of the process.
+    pub fn env(mut self, env: Map<String, String>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Take the values of `get_input(argv)` from `args`, in order.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args.into();
+        self
+    }
+
+    /// Print to `writer` instead of `stdout`.
+    pub fn writer(mut self, writer: impl Write + 'static) -> Self {
+        self.writer = Box::new(writer);
+        self
+    }
+
+    /// Write integers in the base given by `format` when printing them.
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.output_format = format;
+        self
+    }
+
+    /// Write each statement to `trace` before executing it.
+    pub fn trace(mut self, trace: impl Write + 'static) -> Self {
+        self.trace = Some(Box::new(trace));
+        self
+    }
+
+    /// Use integers `width` bits wide, see `with_word_width`.
+    pub fn word_width(mut self, width: WordWidth) -> Self {
+        self.word_width = width;
+        self
+    }
+
+    /// Let programs use the predefined constants as given.
+    pub fn builtins(mut self, builtins: BuiltinConstants) -> Self {
+        self.builtins = builtins;
+        self
+    }
+
+    /// Make `rand()` give the sequence determined by `seed`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = seed;
+        self
+    }
+
+    /// Handle arithmetic overflow according to `mode`.
+    pub fn overflow_mode(mut self, mode: OverflowMode) -> Self {
+        self.overflow_mode = mode;
+        self
+    }
 }
 
-impl Interpreter {
-    fn visit_stmt(&mut self, s: &Stmt) -> u32 {
-        self.program_counter += 1;
-        match s {
-            Stmt::Assignment(identifier, expr) => {
-                let expr = self.visit_expr(expr);
-                self.vars.insert(identifier.lexeme.clone(), expr).unwrap()
-            }
-            Stmt::Store(reg, val) => {
-                let reg = self.visit_expr(reg);
-                let val = self.visit_expr(val);
-                self.registers.insert(reg, val);
-                val
-            }
-            Stmt::Goto(e) => {
-                let e = self.visit_expr(e);
-                self.program_counter = e as usize;
-                e
-            }
-            Stmt::Assert(e) => {
-                let e = self.visit_expr(e);
-                if e == 1 {
-                    e
+/// The labels among `statements`, with their positions counted from `offset`.
+pub(crate) fn labels(
+    statements: &[Stmt],
+    offset: usize,
+) -> impl Iterator<Item = (String, usize)> + '_ {
+    statements
+        .iter()
+        .enumerate()
+        .filter_map(move |(i, stmt)| match &stmt.kind {
+            StmtKind::Label(name) => Some((name.clone(), offset + i)),
+            _ => None,
+        })
+}
+
+/// Read an input word as an integer if possible, then as a float, and otherwise as a string.
+fn input_value(input: String) -> Value {
+    match (input.parse(), input.parse()) {
+        (Ok(v), _) => Value::Int(v),
+        (_, Ok(v)) => Value::Float(v),
+        _ => Value::Str(input),
+    }
+}
+
+impl Visitor<Result<Value, RuntimeError>> for Interpreter {
+    fn visit_stmt(&mut self, s: &Stmt) -> Result<Value, RuntimeError> {
+        let res = match &s.kind {
+            StmtKind::Assignment(identifier, expr)
+            | StmtKind::ConstAssignment(identifier, expr) => {
+                self.check_const(&identifier.lexeme)?;
+                let expr = self.visit_expr(expr)?;
+                let constant = matches!(s.kind, StmtKind::ConstAssignment(..));
+                self.define(&identifier.lexeme, expr, constant)?
+            }
+            StmtKind::Store(reg, val, width) => match &reg.kind {
+                ExprKind::Register(name) => {
+                    let val = self.visit_expr(val)?;
+                    self.store_named(name, val, *width)?
+                }
+                _ => {
+                    let reg = self.visit_expr(reg)?.as_int()?;
+                    let val = self.visit_expr(val)?;
+                    self.store(reg, val, *width)?
+                }
+            },
+            StmtKind::Goto(e) => self.jump(e)?.into(),
+            StmtKind::Call(e) => {
+                self.call_stack.push(self.program_counter);
+                self.jump(e)?.into()
+            }
+            StmtKind::Return => {
+                let target = self
+                    .call_stack
+                    .pop()
+                    .ok_or(RuntimeError::ReturnWithoutCall)?;
+                self.program_counter = target;
+                self.jumped = true;
+                Value::Int(target as u32)
+            }
+            StmtKind::Halt => {
+                self.program_counter = self.statements.len();
+                self.jumped = true;
+                Value::Int(0)
+            }
+            StmtKind::Assert(keyword, e) => match comparison(e) {
+                Some((lhs, op, rhs)) => {
+                    let lhs = self.visit_expr(lhs)?;
+                    let rhs = self.visit_expr(rhs)?;
+                    self.compare(keyword.line, op, lhs, rhs)?
+                }
+                None => {
+                    let e = self.visit_expr(e)?;
+                    assertion(keyword.line, e)?
+                }
+            },
+            StmtKind::IfThenElse(cond, lhs, rhs) => {
+                let cond = self.visit_expr(cond)?;
+                if cond == Value::Int(1) {
+                    self.visit_stmt(lhs)?;
+                } else if cond == Value::Int(0) {
+                    self.visit_stmt(rhs)?;
                 } else {
-                    println!("Assert failed tho, sry.");
-                    std::process::exit(1337);
+                    return Err(RuntimeError::NonBooleanCondition(cond));
                 }
+                Value::Int(0)
+            }
+            StmtKind::Label(_) | StmtKind::Nop => Value::Int(0),
+            StmtKind::Print(e) => {
+                let e = self.visit_expr(e)?;
+                self.print(e)?
+            }
+            StmtKind::Expression(e) => self.visit_expr(e)?,
+            StmtKind::Dump => {
+                self.dump()?;
+                Value::Int(0)
             }
-            Stmt::IfThenElse(cond, lhs, rhs) => {
-                let cond = self.visit_expr(cond);
-                if cond == 1 {
-                    self.visit_expr(lhs)
-                } else if cond == 0 {
-                    self.visit_expr(rhs)
+            StmtKind::While(cond, body) => {
+                loop {
+                    self.step()?;
+                    if !self.visit_expr(cond)?.is_truthy() || !self.visit_body(body)? {
+                        break;
+                    }
+                }
+                Value::Int(0)
+            }
+            StmtKind::Switch(expr, cases, default) => {
+                let value = self.visit_expr(expr)?.as_int()?;
+                let body = cases
+                    .iter()
+                    .find(|(case, _)| *case == value)
+                    .map(|(_, body)| body)
+                    .or(default.as_ref());
+                if let Some(body) = body {
+                    self.visit_body(body)?;
+                }
+                Value::Int(0)
+            }
+            StmtKind::Block(body) => {
+                self.enter_scope();
+                let res = self.visit_body(body);
+                self.exit_scope();
+                res?;
+                Value::Int(0)
+            }
+        };
+        Ok(res)
+    }
+
+    fn visit_expr(&mut self, e: &Expr) -> Result<Value, RuntimeError> {
+        let res = match &e.kind {
+            ExprKind::Load(expr, width) => match &expr.kind {
+                ExprKind::Register(name) => self.load_named(name, *width)?,
+                _ => {
+                    let expr = self.visit_expr(expr)?.as_int()?;
+                    self.load(expr, *width)?
+                }
+            },
+            ExprKind::Binary(lhs, op, rhs) if op.token_type == TokenType::And => {
+                (self.visit_expr(lhs)?.is_truthy() && self.visit_expr(rhs)?.is_truthy()).into()
+            }
+            ExprKind::Binary(lhs, op, rhs) if op.token_type == TokenType::Or => {
+                (self.visit_expr(lhs)?.is_truthy() || self.visit_expr(rhs)?.is_truthy()).into()
+            }
+            ExprKind::Binary(lhs, op, rhs) => {
+                let lhs = self.visit_expr(lhs)?;
+                let rhs = self.visit_expr(rhs)?;
+                self.binary(op, lhs, rhs)?
+            }
+            ExprKind::Unary(op, expr) => {
+                let expr = self.visit_expr(expr)?;
+                self.unary(op, expr)?
+            }
+            ExprKind::Var(identifier) => self.variable(&identifier.lexeme)?,
+            ExprKind::GetInput(channel, key) => self.input(channel, key.as_deref())?,
+            ExprKind::Val(v) => self.literal(*v, e.span.start_line)?,
+            ExprKind::Float(v) => Value::Float(*v),
+            ExprKind::Str(v) => Value::Str(v.clone()),
+            ExprKind::Register(name) => return Err(RuntimeError::NamedRegisterValue(name.clone())),
+            ExprKind::Assign(identifier, expr) => {
+                self.check_const(&identifier.lexeme)?;
+                let expr = self.visit_expr(expr)?;
+                self.define(&identifier.lexeme, expr, false)?
+            }
+            ExprKind::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.visit_expr(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.builtin(name, &args)?
+            }
+            ExprKind::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.visit_expr(item)?.as_int())
+                    .collect::<Result<_, _>>()?,
+            ),
+            ExprKind::Index(array, index) => {
+                let array = self.visit_expr(array)?;
+                let index = self.visit_expr(index)?;
+                element(array, index)?
+            }
+            ExprKind::Conditional(cond, iftrue, iffalse) => {
+                if self.visit_expr(cond)?.is_truthy() {
+                    self.visit_expr(iftrue)?
                 } else {
-                    0
+                    self.visit_expr(iffalse)?
                 }
             }
+        };
+        Ok(res)
+    }
+}
+
+/// The value of an `assert` statement, or an error unless the condition is `1`.
+pub(crate) fn assertion(line: usize, condition: Value) -> Result<Value, RuntimeError> {
+    if condition == Value::Int(1) {
+        Ok(condition)
+    } else {
+        Err(RuntimeError::AssertionFailed {
+            line,
+            value: condition,
+        })
+    }
+}
+
+/// The operands of a comparison, whose values a failing `assert` reports.
+pub(crate) fn comparison(e: &Expr) -> Option<(&Expr, &Token, &Expr)> {
+    match &e.kind {
+        ExprKind::Binary(lhs, op, rhs)
+            if matches!(
+                op.token_type,
+                TokenType::EqualEqual
+                    | TokenType::BangEqual
+                    | TokenType::Less
+                    | TokenType::LessEqual
+                    | TokenType::Greater
+                    | TokenType::GreaterEqual
+            ) =>
+        {
+            Some((lhs, op, rhs))
+        }
+        _ => None,
+    }
+}
+
+/// The element of `array` at `index`.
+pub(crate) fn element(array: Value, index: Value) -> Result<Value, RuntimeError> {
+    let array = match array {
+        Value::Array(array) => array,
+        value => return Err(RuntimeError::ExpectedArray(value)),
+    };
+    let index = index.as_int()?;
+    let len = array.len();
+    array
+        .get(index as usize)
+        .map(|&v| Value::Int(v))
+        .ok_or(RuntimeError::IndexOutOfBounds { index, len })
+}
+
+/// The operations shared by the tree-walking interpreter and the `Vm`,
+/// which keeps its variables, memory and input and output in an `Interpreter`.
+impl Interpreter {
+    /// Fail if `name` is a constant, which may not be assigned again.
+    pub(crate) fn check_const(&self, name: &str) -> Result<(), RuntimeError> {
+        let builtin =
+            self.builtins == BuiltinConstants::Protected && builtin_constant(name).is_some();
+        if builtin || self.consts.contains(name) {
+            Err(RuntimeError::ReassignConst(name.to_owned()))
+        } else {
+            Ok(())
         }
     }
 
-    fn visit_expr(&mut self, e: &Expr) -> u32 {
-        match e {
-            Expr::Load(expr) => {
-                let expr = self.visit_expr(expr);
-                self.registers.get(&expr).unwrap().to_owned()
+    /// Assign `value` to the variable `name`, keeping integers within the word width,
+    /// and make it a constant if `constant` is set. Returns the assigned value.
+    pub(crate) fn define(
+        &mut self,
+        name: &str,
+        value: Value,
+        constant: bool,
+    ) -> Result<Value, RuntimeError> {
+        self.check_const(name)?;
+        let value = match value {
+            Value::Int(v) => Value::Int(v & self.word_width.max()),
+            value => value,
+        };
+        self.assign(name, value.clone());
+        if constant {
+            self.consts.insert(name.to_owned());
+        }
+        Ok(value)
+    }
+
+    /// The value of the variable `name`, or else of the predefined constant.
+    pub(crate) fn variable(&self, name: &str) -> Result<Value, RuntimeError> {
+        self.lookup(name)
+            .cloned()
+            .or_else(|| match self.builtins {
+                BuiltinConstants::Off => None,
+                _ => builtin_constant(name),
+            })
+            .ok_or_else(|| RuntimeError::UndefinedVariable(name.to_owned()))
+    }
+
+    /// Begin a nested scope, whose variables are forgotten by `exit_scope`.
+    pub(crate) fn enter_scope(&mut self) {
+        self.vars.push(Map::new());
+    }
+
+    /// End the innermost nested scope.
+    pub(crate) fn exit_scope(&mut self) {
+        self.vars.pop();
+    }
+
+    /// End every nested scope, leaving only the global one.
+    pub(crate) fn exit_scopes(&mut self) {
+        self.vars.truncate(1);
+    }
+
+    /// Store the low `width` bits of `val` in the register `reg`, returning the stored value.
+    pub(crate) fn store(
+        &mut self,
+        reg: u32,
+        val: Value,
+        width: WordWidth,
+    ) -> Result<Value, RuntimeError> {
+        let word = val.as_int()? & width.max();
+        self.registers.store(reg, word)?;
+        Ok(if width == WordWidth::U32 {
+            val
+        } else {
+            word.into()
+        })
+    }
+
+    /// Load the low `width` bits of the register `reg`.
+    pub(crate) fn load(&self, reg: u32, width: WordWidth) -> Result<Value, RuntimeError> {
+        Ok((self.registers.load(reg)? & width.max()).into())
+    }
+
+    /// Store the low `width` bits of `val` in the named register `@name`,
+    /// returning the stored value. Named registers are kept apart from the memory,
+    /// so they never share a word with a numbered register or with each other.
+    pub(crate) fn store_named(
+        &mut self,
+        name: &str,
+        val: Value,
+        width: WordWidth,
+    ) -> Result<Value, RuntimeError> {
+        let word = val.as_int()? & width.max();
+        self.named_registers.insert(name.to_owned(), word);
+        Ok(if width == WordWidth::U32 {
+            val
+        } else {
+            word.into()
+        })
+    }
+
+    /// Load the low `width` bits of the named register `@name`.
+    pub(crate) fn load_named(&self, name: &str, width: WordWidth) -> Result<Value, RuntimeError> {
+        match self.named_registers.get(name) {
+            Some(word) => Ok((word & width.max()).into()),
+            None => Err(RuntimeError::UndefinedNamedRegister(name.to_owned())),
+        }
+    }
+
+    /// Write a value to the output, unless that would exceed the output limit.
+    pub(crate) fn print(&mut self, value: Value) -> Result<Value, RuntimeError> {
+        if let Some(limit) = self.output_limit {
+            if self.outputs >= limit {
+                return Err(RuntimeError::OutputLimitExceeded(limit));
             }
-            Expr::Binary(lhs, op, rhs) => {
-                let lhs = self.visit_expr(lhs);
-                let rhs = self.visit_expr(rhs);
-                match &op.token_type {
-                    crate::tokens::TokenType::Plus => lhs + rhs,
-                    crate::tokens::TokenType::Minus => lhs - rhs,
-                    crate::tokens::TokenType::Star => lhs * rhs,
-                    crate::tokens::TokenType::Slash => lhs / rhs,
-                    t => panic!("Invalid binary token: {:#?}", t),
+        }
+        self.outputs += 1;
+        let text = value.render(self.output_format);
+        writeln!(self.writer, "{}", text).map_err(|err| RuntimeError::Output(err.to_string()))?;
+        self.output.push(text);
+        Ok(value)
+    }
+
+    /// The integer literal `value` on `line`, which must fit in the word width.
+    pub(crate) fn literal(&self, value: u32, line: usize) -> Result<Value, RuntimeError> {
+        if value > self.word_width.max() {
+            return Err(RuntimeError::LiteralTooWide { line, value });
+        }
+        Ok(Value::Int(value))
+    }
+
+    /// Write the variables in scope and the stored registers to the output,
+    /// e.g. `vars: x=7, y=1` and `registers: 2=6, @r0=5`.
+    /// Unlike `print`, this does not count towards the output limit.
+    pub(crate) fn dump(&mut self) -> Result<(), RuntimeError> {
+        let vars: Vec<_> = self
+            .vars()
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value.render(self.output_format)))
+            .collect();
+        let registers: Vec<_> = self
+            .registers
+            .entries()
+            .into_iter()
+            .map(|(addr, value)| {
+                format!("{}={}", addr, Value::Int(value).render(self.output_format))
+            })
+            .chain(self.named_registers().into_iter().map(|(name, value)| {
+                format!("@{}={}", name, Value::Int(value).render(self.output_format))
+            }))
+            .collect();
+        for line in [
+            format!("vars: {}", vars.join(", ")),
+            format!("registers: {}", registers.join(", ")),
+        ] {
+            writeln!(self.writer, "{}", line)
+                .map_err(|err| RuntimeError::Output(err.to_string()))?;
+            self.output.push(line);
+        }
+        Ok(())
+    }
+
+    /// Read a value from the input `channel`, where `key` names the variable read from `env`.
+    pub(crate) fn input(
+        &mut self,
+        channel: &str,
+        key: Option<&str>,
+    ) -> Result<Value, RuntimeError> {
+        let value = match channel {
+            "stdin" if self.inputs.is_some() => self
+                .inputs
+                .as_mut()
+                .and_then(VecDeque::pop_front)
+                .map(Value::Int)
+                .ok_or(RuntimeError::InputExhausted)?,
+            "stdin" => input_value(self.read_word()?),
+            "argv" => input_value(self.args.pop_front().ok_or(RuntimeError::InputExhausted)?),
+            "env" => {
+                let name = key.unwrap_or_default();
+                let value = match &self.env {
+                    Some(env) => env.get(name).cloned(),
+                    None => std::env::var(name).ok(),
                 }
+                .ok_or_else(|| RuntimeError::UndefinedEnvVar(name.to_owned()))?;
+                Value::Int(
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| RuntimeError::InvalidInput(value))?,
+                )
             }
-            Expr::Unary(_, expr) => {
-                let expr = self.visit_expr(expr);
-                expr
+            _ => return Err(RuntimeError::UnknownChannel(channel.to_owned())),
+        };
+        Ok(value)
+    }
+
+    /// Compare the operands of an `assert`, failing with both values unless the comparison holds.
+    pub(crate) fn compare(
+        &self,
+        line: usize,
+        op: &Token,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<Value, RuntimeError> {
+        match self.binary(op, lhs.clone(), rhs.clone())? {
+            Value::Int(1) => Ok(Value::Int(1)),
+            _ => Err(RuntimeError::ComparisonFailed {
+                line,
+                op: op.lexeme.clone(),
+                lhs,
+                rhs,
+            }),
+        }
+    }
+
+    /// Apply a binary operator, other than the short-circuiting `and` and `or`.
+    pub(crate) fn binary(&self, op: &Token, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+        match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => Ok(self.binary_int(op, lhs, rhs)?.into()),
+            (Value::Str(lhs), Value::Str(rhs)) => Self::binary_str(op, lhs, rhs),
+            (lhs, rhs) => Self::binary_float(op, lhs.as_float()?, rhs.as_float()?),
+        }
+    }
+
+    /// Apply a unary operator.
+    pub(crate) fn unary(&self, op: &Token, val: Value) -> Result<Value, RuntimeError> {
+        let res = match (&op.token_type, val) {
+            (crate::tokens::TokenType::Plus, val) => val,
+            (crate::tokens::TokenType::Minus, Value::Int(val)) => self.negate(op.line, val)?.into(),
+            (crate::tokens::TokenType::Minus, Value::Float(val)) => Value::Float(-val),
+            (crate::tokens::TokenType::Minus, Value::Str(_) | Value::Array(_)) => {
+                return Err(RuntimeError::InvalidOperands {
+                    line: op.line,
+                    op: op.lexeme.clone(),
+                })
+            }
+            (crate::tokens::TokenType::Not, val) => (!val.is_truthy()).into(),
+            _ => {
+                return Err(RuntimeError::InvalidOperator {
+                    line: op.line,
+                    op: op.lexeme.clone(),
+                })
             }
-            Expr::Var(identifier) => self.vars.get(identifier).unwrap().clone(),
-            Expr::GetInput(_) => {
-                let mut buffer = String::new();
-                use std::io::{self, Read};
-                let stdin = io::stdin();
-                let mut handle = stdin.lock();
+        };
+        Ok(res)
+    }
 
-                handle.read_to_string(&mut buffer).unwrap();
+    /// Apply a binary operator to two integers.
+    fn binary_int(&self, op: &Token, lhs: u32, rhs: u32) -> Result<u32, RuntimeError> {
+        let res = match &op.token_type {
+            crate::tokens::TokenType::Plus => self.arithmetic(
+                op.line,
+                lhs,
+                rhs,
+                u32::wrapping_add,
+                u32::checked_add,
+                u32::saturating_add,
+            )?,
+            crate::tokens::TokenType::Minus => {
+                if lhs < rhs && self.overflow_mode == OverflowMode::Wrapping {
+                    event!(
+                        Level::WARN,
+                        "[line {}] {} - {} underflows and wraps around",
+                        op.line,
+                        lhs,
+                        rhs
+                    );
+                }
+                self.arithmetic(
+                    op.line,
+                    lhs,
+                    rhs,
+                    u32::wrapping_sub,
+                    u32::checked_sub,
+                    u32::saturating_sub,
+                )?
+            }
+            crate::tokens::TokenType::Star => self.arithmetic(
+                op.line,
+                lhs,
+                rhs,
+                u32::wrapping_mul,
+                u32::checked_mul,
+                u32::saturating_mul,
+            )?,
+            crate::tokens::TokenType::StarStar => self.arithmetic(
+                op.line,
+                lhs,
+                rhs,
+                u32::wrapping_pow,
+                u32::checked_pow,
+                u32::saturating_pow,
+            )?,
+            crate::tokens::TokenType::Slash => match lhs.checked_div(rhs) {
+                Some(v) => v,
+                None => return Err(RuntimeError::DivisionByZero { line: op.line }),
+            },
+            crate::tokens::TokenType::Percent => match lhs.checked_rem(rhs) {
+                Some(v) => v,
+                None => return Err(RuntimeError::DivisionByZero { line: op.line }),
+            },
+            crate::tokens::TokenType::Ampersand => lhs & rhs,
+            crate::tokens::TokenType::Pipe => lhs | rhs,
+            crate::tokens::TokenType::Caret => lhs ^ rhs,
+            crate::tokens::TokenType::LessLess => lhs.wrapping_shl(rhs) & self.word_width.max(),
+            crate::tokens::TokenType::GreaterGreater => lhs.wrapping_shr(rhs),
+            crate::tokens::TokenType::Less => (lhs < rhs) as u32,
+            crate::tokens::TokenType::LessEqual => (lhs <= rhs) as u32,
+            crate::tokens::TokenType::Greater => (lhs > rhs) as u32,
+            crate::tokens::TokenType::GreaterEqual => (lhs >= rhs) as u32,
+            crate::tokens::TokenType::EqualEqual => (lhs == rhs) as u32,
+            crate::tokens::TokenType::BangEqual => (lhs != rhs) as u32,
+            _ => {
+                return Err(RuntimeError::InvalidOperator {
+                    line: op.line,
+                    op: op.lexeme.clone(),
+                })
+            }
+        };
+        Ok(res)
+    }
+
+    /// Apply the built-in function `name` to already evaluated arguments.
+    /// Integers are only treated as floats when another argument is a float.
+    /// The bit-counting functions only take integers, and count within the word width.
+    /// `rand()` advances the random number generator, while `steps()` and `pc()`
+    /// give the steps counted so far and the index of the top-level statement.
+    pub(crate) fn builtin(&mut self, name: &Token, args: &[Value]) -> Result<Value, RuntimeError> {
+        let max = self.word_width.max();
+        let res = match (name.lexeme.as_str(), args) {
+            ("min", [Value::Int(a), Value::Int(b)]) => Value::Int(*a.min(b)),
+            ("max", [Value::Int(a), Value::Int(b)]) => Value::Int(*a.max(b)),
+            ("min", [a, b]) => Value::Float(a.as_float()?.min(b.as_float()?)),
+            ("max", [a, b]) => Value::Float(a.as_float()?.max(b.as_float()?)),
+            ("abs", [Value::Int(a)]) => Value::Int(*a),
+            ("abs", [a]) => Value::Float(a.as_float()?.abs()),
+            ("isqrt", [a]) => Value::Int(a.as_int()?.isqrt()),
+            ("popcount", [a]) => Value::Int(a.as_int()?.count_ones()),
+            ("clz", [a]) => Value::Int(
+                a.as_int()?
+                    .leading_zeros()
+                    .saturating_sub(max.leading_zeros()),
+            ),
+            ("ctz", [a]) => Value::Int(a.as_int()?.trailing_zeros().min(max.count_ones())),
+            ("rand", []) => Value::Int(self.rand() & max),
+            ("steps", []) => Value::Int(self.steps.min(max as usize) as u32),
+            ("pc", []) => Value::Int(self.program_counter.saturating_sub(1) as u32),
+            ("get_input_line", []) => Value::Array(self.read_line()?),
+            _ => {
+                return Err(RuntimeError::InvalidCall {
+                    line: name.line,
+                    name: name.lexeme.clone(),
+                })
+            }
+        };
+        Ok(res)
+    }
+
+    /// Apply a binary operator to two floats, at least one of which was promoted from an integer.
+    fn binary_float(op: &Token, lhs: f64, rhs: f64) -> Result<Value, RuntimeError> {
+        let res = match &op.token_type {
+            crate::tokens::TokenType::Plus => Value::Float(lhs + rhs),
+            crate::tokens::TokenType::Minus => Value::Float(lhs - rhs),
+            crate::tokens::TokenType::Star => Value::Float(lhs * rhs),
+            crate::tokens::TokenType::StarStar => Value::Float(lhs.powf(rhs)),
+            crate::tokens::TokenType::Slash => Value::Float(lhs / rhs),
+            crate::tokens::TokenType::Percent => Value::Float(lhs % rhs),
+            crate::tokens::TokenType::Less => (lhs < rhs).into(),
+            crate::tokens::TokenType::LessEqual => (lhs <= rhs).into(),
+            crate::tokens::TokenType::Greater => (lhs > rhs).into(),
+            crate::tokens::TokenType::GreaterEqual => (lhs >= rhs).into(),
+            crate::tokens::TokenType::EqualEqual => (lhs == rhs).into(),
+            crate::tokens::TokenType::BangEqual => (lhs != rhs).into(),
+            _ => {
+                return Err(RuntimeError::InvalidOperands {
+                    line: op.line,
+                    op: op.lexeme.clone(),
+                })
+            }
+        };
+        Ok(res)
+    }
+
+    /// Apply a binary operator to two strings. `+` concatenates them.
+    fn binary_str(op: &Token, lhs: String, rhs: String) -> Result<Value, RuntimeError> {
+        let res = match &op.token_type {
+            crate::tokens::TokenType::Plus => Value::Str(lhs + &rhs),
+            crate::tokens::TokenType::EqualEqual => (lhs == rhs).into(),
+            crate::tokens::TokenType::BangEqual => (lhs != rhs).into(),
+            _ => {
+                return Err(RuntimeError::InvalidOperands {
+                    line: op.line,
+                    op: op.lexeme.clone(),
+                })
+            }
+        };
+        Ok(res)
+    }
+
+    /// Move execution to the statement indicated by `e`.
+    /// Jumping to the end of the program is allowed, and terminates it.
+    fn jump(&mut self, e: &Expr) -> Result<u32, RuntimeError> {
+        let target = self.jump_target(e)?;
+        if target as usize > self.statements.len() {
+            return Err(RuntimeError::InvalidJumpTarget(target));
+        }
+        self.program_counter = target as usize;
+        self.jumped = true;
+        Ok(target)
+    }
 
-                let val: u32 = buffer.parse().unwrap();
-                val
+    /// Find a variable, searching from the innermost scope outwards.
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.vars.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Update the variable in the innermost scope which has it,
+    /// or define it in the innermost scope if none does.
+    fn assign(&mut self, name: &str, value: Value) {
+        let scope = match self.vars.iter().rposition(|scope| scope.contains_key(name)) {
+            Some(i) => &mut self.vars[i],
+            None => self
+                .vars
+                .last_mut()
+                .expect("the global scope is never popped"),
+        };
+        scope.insert(name.to_owned(), value);
+    }
+
+    /// Resolve the target of a goto, either through a label or by evaluating it.
+    fn jump_target(&mut self, e: &Expr) -> Result<u32, RuntimeError> {
+        match &e.kind {
+            ExprKind::Var(name) if self.labels.contains_key(&name.lexeme) => {
+                Ok(self.labels[&name.lexeme] as u32)
             }
-            Expr::Val(v) => v.clone(),
+            ExprKind::Var(name) if self.variable(&name.lexeme).is_err() => {
+                Err(RuntimeError::UndefinedLabel(name.lexeme.clone()))
+            }
+            _ => self.visit_expr(e)?.as_int(),
         }
     }
+
+    /// Read the rest of the current line of the input as whitespace-separated integers.
+    /// The values given with `with_inputs` count as a single line.
+    fn read_line(&mut self) -> Result<Vec<u32>, RuntimeError> {
+        if let Some(inputs) = &mut self.inputs {
+            if inputs.is_empty() {
+                return Err(RuntimeError::InputExhausted);
+            }
+            return Ok(inputs.drain(..).collect());
+        }
+        let mut line = String::new();
+        let read = self
+            .reader
+            .read_line(&mut line)
+            .map_err(|err| RuntimeError::Input(err.to_string()))?;
+        if read == 0 {
+            return Err(RuntimeError::InputExhausted);
+        }
+        line.split_whitespace()
+            .map(|word| {
+                word.parse()
+                    .map_err(|_| RuntimeError::InvalidInput(word.to_owned()))
+            })
+            .collect()
+    }
+
+    /// Read the next whitespace-delimited word from the input.
+    fn read_word(&mut self) -> Result<String, RuntimeError> {
+        let mut word = Vec::new();
+        loop {
+            let buffer = self
+                .reader
+                .fill_buf()
+                .map_err(|err| RuntimeError::Input(err.to_string()))?;
+            if buffer.is_empty() {
+                break;
+            }
+
+            let mut used = 0;
+            let mut done = false;
+            for &byte in buffer {
+                used += 1;
+                if !byte.is_ascii_whitespace() {
+                    word.push(byte);
+                } else if !word.is_empty() {
+                    done = true;
+                    break;
+                }
+            }
+            self.reader.consume(used);
+            if done {
+                break;
+            }
+        }
+
+        if word.is_empty() {
+            Err(RuntimeError::InputExhausted)
+        } else {
+            Ok(String::from_utf8_lossy(&word).into_owned())
+        }
+    }
+
+    /// The next value of a linear congruential generator, taken from its high bits.
+    fn rand(&mut self) -> u32 {
+        self.rng = self
+            .rng
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.rng >> 32) as u32
+    }
+
+    /// Negate a value according to the current `OverflowMode`.
+    fn negate(&self, line: usize, val: u32) -> Result<u32, RuntimeError> {
+        match self.overflow_mode {
+            OverflowMode::Wrapping => Ok(val.wrapping_neg() & self.word_width.max()),
+            OverflowMode::Checked => val.checked_neg().ok_or(RuntimeError::Overflow { line }),
+            OverflowMode::Saturating => Ok(0),
+        }
+    }
+
+    /// Apply the operation variant matching the current `OverflowMode`,
+    /// treating results beyond the word width as overflowing.
+    fn arithmetic(
+        &self,
+        line: usize,
+        lhs: u32,
+        rhs: u32,
+        wrapping: fn(u32, u32) -> u32,
+        checked: fn(u32, u32) -> Option<u32>,
+        saturating: fn(u32, u32) -> u32,
+    ) -> Result<u32, RuntimeError> {
+        let max = self.word_width.max();
+        match self.overflow_mode {
+            OverflowMode::Wrapping => Ok(wrapping(lhs, rhs) & max),
+            OverflowMode::Checked => checked(lhs, rhs)
+                .filter(|&v| v <= max)
+                .ok_or(RuntimeError::Overflow { line }),
+            OverflowMode::Saturating => Ok(saturating(lhs, rhs).min(max)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+    use std::cell::RefCell;
+
+    /// A writer whose contents can be inspected after the interpreter is consumed.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    fn interpret(src: &str) -> Result<Vec<Value>, RuntimeError> {
+        Interpreter::new(Parser::new(Scanner::new(src)).collect()).visit()
+    }
+
+    fn ints(values: &[u32]) -> Vec<Value> {
+        values.iter().copied().map(Value::Int).collect()
+    }
+
+    fn interpret_with(src: &str, mode: OverflowMode) -> Result<Vec<Value>, RuntimeError> {
+        Interpreter::with_overflow_mode(Parser::new(Scanner::new(src)).collect(), mode).visit()
+    }
+
+    #[test]
+    fn interpret_assignment() {
+        assert_eq!(interpret("x := 5"), Ok(ints(&[5])));
+    }
+
+    #[test]
+    fn interpret_word_width() {
+        let interpret_u8 = |src, mode| {
+            let stmts = Parser::new(Scanner::with_word_width(src, WordWidth::U8)).collect();
+            Interpreter {
+                overflow_mode: mode,
+                ..Interpreter::with_word_width(stmts, WordWidth::U8)
+            }
+            .visit()
+        };
+        let wrapping = OverflowMode::Wrapping;
+        assert_eq!(interpret_u8("x := 255 + 1", wrapping), Ok(ints(&[0])));
+        assert_eq!(interpret_u8("x := 0 - 1", wrapping), Ok(ints(&[255])));
+        assert_eq!(interpret_u8("x := -1", wrapping), Ok(ints(&[255])));
+        assert_eq!(interpret_u8("x := 16 * 17", wrapping), Ok(ints(&[16])));
+        assert_eq!(interpret_u8("x := 1 << 8", wrapping), Ok(ints(&[0])));
+        assert_eq!(
+            interpret_u8("x := 255 + 1", OverflowMode::Checked),
+            Err(RuntimeError::Overflow { line: 1 })
+        );
+        assert_eq!(
+            interpret_u8("x := 200 + 100", OverflowMode::Saturating),
+            Ok(ints(&[255]))
+        );
+    }
+
+    #[test]
+    fn interpret_power() {
+        assert_eq!(interpret("x := 2 ** 10"), Ok(ints(&[1024])));
+        assert_eq!(interpret("x := 2 ** 3 ** 2"), Ok(ints(&[512])));
+        assert_eq!(interpret("x := 2 ** 32"), Ok(ints(&[0])));
+        assert_eq!(
+            interpret_with("x := 2 ** 32", OverflowMode::Checked),
+            Err(RuntimeError::Overflow { line: 1 })
+        );
+        assert_eq!(interpret("x := 4.0 ** 0.5"), Ok(vec![Value::Float(2.0)]));
+    }
+
+    #[test]
+    fn interpret_floats() {
+        assert_eq!(interpret("x := 1.5 + 2.5"), Ok(vec![Value::Float(4.0)]));
+        assert_eq!(interpret("x := 1 + 2.0"), Ok(vec![Value::Float(3.0)]));
+        assert_eq!(
+            interpret("x := 1 / 0.0"),
+            Ok(vec![Value::Float(f64::INFINITY)])
+        );
+        assert_eq!(interpret("x := 0.5 < 1"), Ok(ints(&[1])));
+        assert_eq!(interpret("x := -0.5"), Ok(vec![Value::Float(-0.5)]));
+    }
+
+    #[test]
+    fn interpret_strings() {
+        assert_eq!(
+            interpret(r#"x := "foo" + "bar""#),
+            Ok(vec![Value::Str("foobar".into())])
+        );
+        assert_eq!(interpret(r#"x := "a" == "a""#), Ok(ints(&[1])));
+        assert_eq!(
+            interpret(r#"x := "a" + 1"#),
+            Err(RuntimeError::ExpectedNumber(Value::Str("a".into())))
+        );
+        assert_eq!(
+            interpret(r#"x := "a" * "b""#),
+            Err(RuntimeError::InvalidOperands {
+                line: 1,
+                op: "*".into()
+            })
+        );
+    }
+
+    #[test]
+    fn interpret_float_errors() {
+        assert_eq!(
+            interpret("x := 1.5 & 1"),
+            Err(RuntimeError::InvalidOperands {
+                line: 1,
+                op: "&".into()
+            })
+        );
+        assert_eq!(
+            interpret("store(1.5, 1)"),
+            Err(RuntimeError::ExpectedInt(Value::Float(1.5)))
+        );
+    }
+
+    #[test]
+    fn interpret_comparisons() {
+        assert_eq!(
+            interpret("a := 1 < 2 b := 2 <= 1 c := 3 > 2 d := 2 >= 3 e := 4 == 4 f := 4 != 4"),
+            Ok(ints(&[1, 0, 1, 0, 1, 0]))
+        );
+    }
+
+    #[test]
+    fn interpret_bitwise() {
+        assert_eq!(interpret("x := 6 & 3"), Ok(ints(&[2])));
+        assert_eq!(interpret("x := 6 | 3"), Ok(ints(&[7])));
+        assert_eq!(interpret("x := 6 ^ 3"), Ok(ints(&[5])));
+        assert_eq!(interpret("x := 1 << 4"), Ok(ints(&[16])));
+        assert_eq!(interpret("x := 255 >> 1"), Ok(ints(&[127])));
+        assert_eq!(interpret("x := 1 << 33"), Ok(ints(&[2])));
+        assert_eq!(interpret("x := 4 >> 34"), Ok(ints(&[1])));
+    }
+
+    #[test]
+    fn interpret_remainder() {
+        assert_eq!(interpret("x := 7 % 3"), Ok(ints(&[1])));
+        assert_eq!(
+            interpret("x := 5 % 0"),
+            Err(RuntimeError::DivisionByZero { line: 1 })
+        );
+    }
+
+    #[test]
+    fn interpret_undefined_variable() {
+        assert_eq!(
+            interpret("store(0, y)"),
+            Err(RuntimeError::UndefinedVariable("y".into()))
+        );
+    }
+
+    #[test]
+    fn interpret_goto_numeric() {
+        assert_eq!(
+            interpret("x := 0 goto 3 x := 1 store(0, x)"),
+            Ok(ints(&[0]))
+        );
+    }
+
+    #[test]
+    fn interpret_results_skip_jumps() {
+        assert_eq!(interpret("goto 1 goto 2 1 + 2"), Ok(ints(&[3])));
+        assert_eq!(interpret("store(0, 1) label l: nop { x := 1 }"), Ok(vec![]));
+    }
+
+    #[test]
+    fn interpret_goto_label() {
+        assert_eq!(
+            interpret("x := 0 goto skip x := 1 label skip: store(0, x)"),
+            Ok(ints(&[0]))
+        );
+    }
+
+    #[test]
+    fn interpret_step_limit() {
+        let stmts = Parser::new(Scanner::new("goto 0")).collect();
+        let res = Interpreter::with_step_limit(stmts, 100).visit();
+        assert_eq!(res, Err(RuntimeError::StepLimitExceeded(100)));
+
+        let stmts = Parser::new(Scanner::new("x := 1 y := 2")).collect();
+        let res = Interpreter::with_step_limit(stmts, 2).visit();
+        assert_eq!(res, Ok(ints(&[1, 2])));
+    }
+
+    #[test]
+    fn interpret_while() {
+        assert_eq!(
+            interpret("i := 0 while i < 5 do i := i + 1 end x := i"),
+            Ok(ints(&[0, 5]))
+        );
+        assert_eq!(
+            interpret("i := 0 while 1 do i := i + 1 goto done end label done: x := i"),
+            Ok(ints(&[0, 1]))
+        );
+    }
+
+    #[test]
+    fn interpret_trace() {
+        let output = Output::default();
+        let stmts = Parser::new(Scanner::new("x := 1 goto 2")).collect();
+        let res = Interpreter::with_trace(stmts, output.clone()).visit();
+        assert_eq!(res, Ok(ints(&[1])));
+        assert_eq!(
+            output.contents(),
+            "pc=0: x := 1\npc=1: goto 2\npc=1: -> 2\n"
+        );
+    }
+
+    /// Records the reference count of a statement whenever it is traced.
+    struct Counts(Rc<Stmt>, Rc<RefCell<Vec<usize>>>);
+
+    impl Write for Counts {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.starts_with(b"pc=1: ") {
+                self.1.borrow_mut().push(Rc::strong_count(&self.0));
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn interpret_shares_statements() {
+        let src = "i := 0 i := i + 1 if i < 50 then goto 1 else goto 3 x := i";
+        let mut interpreter = Interpreter::new(Parser::new(Scanner::new(src)).collect());
+        let counts = Rc::new(RefCell::new(Vec::new()));
+        let stmt = Rc::clone(&interpreter.statements[1]);
+        interpreter.trace = Some(Box::new(Counts(stmt, Rc::clone(&counts))));
+        interpreter.run().unwrap();
+        // The program, the trace and the running step share one allocation.
+        assert_eq!(*counts.borrow(), vec![3; 50]);
+        interpreter.trace = None;
+        assert_eq!(Rc::strong_count(&interpreter.statements[1]), 1);
+    }
+
+    #[test]
+    fn interpret_errors() {
+        assert_eq!(
+            interpret("x := y"),
+            Err(RuntimeError::UndefinedVariable("y".into()))
+        );
+        assert_eq!(
+            interpret("x := load(3)"),
+            Err(RuntimeError::UndefinedRegister(3))
+        );
+        assert_eq!(
+            interpret("x := 1\ny := x / 0"),
+            Err(RuntimeError::DivisionByZero { line: 2 })
+        );
+        assert_eq!(interpret("goto 7"), Err(RuntimeError::InvalidJumpTarget(7)));
+    }
+
+    #[test]
+    fn interpret_invalid_operator() {
+        let star = Token {
+            token_type: TokenType::Star,
+            lexeme: "*".into(),
+            line: 1,
+            column: 1,
+        };
+        let unary = ExprKind::Unary(star, Box::new(ExprKind::Val(1).into()));
+        let stmts = vec![StmtKind::Print(Box::new(unary.into())).into()];
+        assert_eq!(
+            Interpreter::new(stmts).visit(),
+            Err(RuntimeError::InvalidOperator {
+                line: 1,
+                op: "*".into()
+            })
+        );
+    }
+
+    #[test]
+    fn interpret_assert() {
+        assert_eq!(interpret("assert 1 == 1"), Ok(vec![]));
+        assert_eq!(
+            interpret("x := 1\nassert 0"),
+            Err(RuntimeError::AssertionFailed {
+                line: 2,
+                value: Value::Int(0)
+            })
+        );
+    }
+
+    #[test]
+    fn interpret_chained_assignment() {
+        let outcome = Interpreter::new(Parser::new(Scanner::new("a := b := 5")).collect())
+            .run_to_completion()
+            .unwrap();
+        assert_eq!(outcome.vars["a"], Value::Int(5));
+        assert_eq!(outcome.vars["b"], Value::Int(5));
+        assert_eq!(outcome.results, ints(&[5]));
+        assert_eq!(
+            interpret("const b := 1 a := b := 2"),
+            Err(RuntimeError::ReassignConst("b".into()))
+        );
+    }
+
+    #[test]
+    fn interpret_builtin_constants() {
+        assert_eq!(interpret("x := MAX_U32"), Ok(ints(&[4294967295])));
+        assert_eq!(
+            interpret("x := MAX_U8 + MAX_U16 + MIN_U32 y := PI > 3.14"),
+            Ok(ints(&[65790, 1]))
+        );
+        assert_eq!(interpret("MAX_U32 := 5 x := MAX_U32"), Ok(ints(&[5, 5])));
+
+        let run = |src, builtins| {
+            Interpreter::with_builtins(Parser::new(Scanner::new(src)).collect(), builtins).visit()
+        };
+        assert_eq!(
+            run("x := MAX_U32", BuiltinConstants::Off),
+            Err(RuntimeError::UndefinedVariable("MAX_U32".into()))
+        );
+        assert_eq!(
+            run("MAX_U32 := 5", BuiltinConstants::Protected),
+            Err(RuntimeError::ReassignConst("MAX_U32".into()))
+        );
+        assert_eq!(
+            run("x := MAX_U32 - 1", BuiltinConstants::Protected),
+            Ok(ints(&[u32::MAX - 1]))
+        );
+    }
+
+    #[test]
+    fn interpret_const() {
+        assert_eq!(
+            interpret("const x := 5 x := 6"),
+            Err(RuntimeError::ReassignConst("x".into()))
+        );
+        assert_eq!(
+            interpret("const x := 5 const x := 6"),
+            Err(RuntimeError::ReassignConst("x".into()))
+        );
+        assert_eq!(
+            interpret("const x := 5 y := 1 y := x + y"),
+            Ok(ints(&[5, 1, 6]))
+        );
+    }
+
+    #[test]
+    fn interpret_run_to_completion() {
+        let stmts = Parser::new(Scanner::new("x := 5; y := x + 1; store(2, y)")).collect();
+        let outcome = Interpreter::new(stmts).run_to_completion().unwrap();
+        assert!(outcome.output.is_empty());
+        let vars: Vec<_> = outcome
+            .vars
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        assert_eq!(vars, vec!["x=5", "y=6"]);
+        assert_eq!(outcome.registers, BTreeMap::from([(2, 6)]));
+        assert_eq!(outcome.results, ints(&[5, 6]));
+
+        let stmts = Parser::new(Scanner::new("x := 7 print x dump print \"a\"")).collect();
+        let outcome = Interpreter::with_writer(stmts, io::sink())
+            .run_to_completion()
+            .unwrap();
+        assert_eq!(outcome.output, vec!["7", "vars: x=7", "registers: ", "a"]);
+
+        let stmts = Parser::new(Scanner::new("{ x := 1 } y := 1 / 0")).collect();
+        let res = Interpreter::new(stmts).run_to_completion();
+        assert_eq!(res, Err(RuntimeError::DivisionByZero { line: 1 }));
+    }
+
+    #[test]
+    fn interpret_expression_statement() {
+        assert_eq!(interpret("1 + 2"), Ok(ints(&[3])));
+        assert_eq!(interpret("x := 4 x * 2 x"), Ok(ints(&[4, 8, 4])));
+    }
+
+    #[test]
+    fn interpret_nop() {
+        let run = |src| {
+            let stmts = Parser::new(Scanner::new(src)).collect();
+            Interpreter::new(stmts).run_to_completion().unwrap()
+        };
+        let with_nop = run("x := 1 nop y := x + 1");
+        assert_eq!(with_nop.vars, run("x := 1 y := x + 1").vars);
+        assert_eq!(with_nop.results, ints(&[1, 2]));
+        // The nop is statement 1, so the jump lands on the assignment after it.
+        assert_eq!(run("goto 2 nop x := 1 nop").results, ints(&[1]));
+    }
+
+    #[test]
+    fn interpret_execute() {
+        let mut interpreter = Interpreter::new(vec![]);
+        let parse = |src| Parser::new(Scanner::new(src)).collect();
+        assert_eq!(
+            interpreter.execute(parse("x := 1 label l:")),
+            Ok(ints(&[1]))
+        );
+        assert_eq!(interpreter.execute(parse("y := x + 1")), Ok(ints(&[2])));
+        assert_eq!(interpreter.execute(parse("store(y, x)")), Ok(vec![]));
+        assert_eq!(interpreter.labels["l"], 1);
+        let vars: Vec<_> = interpreter.vars().into_iter().collect();
+        assert_eq!(vars, vec![("x", &Value::Int(1)), ("y", &Value::Int(2))]);
+        assert_eq!(interpreter.registers().load(2), Ok(1));
+    }
+
+    #[test]
+    fn interpret_if_statements() {
+        let run = |src| {
+            Interpreter::new(Parser::new(Scanner::new(src)).collect())
+                .run_to_completion()
+                .map(|outcome| outcome.vars)
+        };
+        let vars = run("if 1 then x := 2 else x := 3").unwrap();
+        assert_eq!(vars["x"], Value::Int(2));
+        let vars = run("if 0 then x := 2 else x := 3").unwrap();
+        assert_eq!(vars["x"], Value::Int(3));
+        let vars = run("x := 1 if x then halt else x := 3 x := 4").unwrap();
+        assert_eq!(vars["x"], Value::Int(1));
+    }
+
+    #[test]
+    fn interpret_reset() {
+        let src = "x := 1 store(1, 5) label l: x := x + load(1) y := rand() print steps()";
+        let mut interpreter =
+            Interpreter::with_writer(Parser::new(Scanner::new(src)).collect(), io::sink());
+        let first = interpreter.run().unwrap();
+        assert_eq!(first[..2], ints(&[1, 6]));
+        assert_eq!(interpreter.run(), Ok(vec![]));
+
+        interpreter.reset();
+        assert!(interpreter.vars().is_empty());
+        assert!(interpreter.registers().entries().is_empty());
+        assert_eq!(interpreter.run(), Ok(first));
+        assert_eq!(interpreter.vars()["x"], &Value::Int(6));
+
+        let mut interpreter = Interpreter::new(Parser::new(Scanner::new("const c := 1")).collect());
+        interpreter.run().unwrap();
+        interpreter.reset();
+        assert_eq!(interpreter.run(), Ok(ints(&[1])));
+    }
+
+    #[test]
+    fn interpret_halt() {
+        assert_eq!(interpret("x := 1 halt y := 2"), Ok(ints(&[1])));
+        assert_eq!(interpret("while 1 do halt end x := 1"), Ok(vec![]));
+    }
+
+    #[test]
+    fn interpret_call_return() {
+        let src = "x := 0 call inc call inc goto done \
+                   label inc: x := x + 1 return \
+                   label done: y := x";
+        assert_eq!(interpret(src), Ok(ints(&[0, 1, 2, 2])));
+        assert_eq!(interpret("return"), Err(RuntimeError::ReturnWithoutCall));
+    }
+
+    #[test]
+    fn interpret_block_scope() {
+        assert_eq!(
+            interpret("x := 1 { y := 2 x := x + y } z := x"),
+            Ok(ints(&[1, 3]))
+        );
+        assert_eq!(
+            interpret("{ y := 2 } z := y"),
+            Err(RuntimeError::UndefinedVariable("y".into()))
+        );
+    }
+
+    #[test]
+    fn interpret_array() {
+        assert_eq!(interpret("x := [10,20,30][1]"), Ok(ints(&[20])));
+        assert_eq!(
+            interpret("a := [1, 2 + 3] i := 1 x := a[i] * 2"),
+            Ok(vec![
+                Value::Array(vec![1, 5]),
+                Value::Int(1),
+                Value::Int(10)
+            ])
+        );
+        assert_eq!(
+            interpret("x := [10, 20][2]"),
+            Err(RuntimeError::IndexOutOfBounds { index: 2, len: 2 })
+        );
+        assert_eq!(
+            interpret("x := 5[0]"),
+            Err(RuntimeError::ExpectedArray(Value::Int(5)))
+        );
+        assert_eq!(
+            interpret("x := [1.5]"),
+            Err(RuntimeError::ExpectedInt(Value::Float(1.5)))
+        );
+    }
+
+    #[test]
+    fn interpret_switch() {
+        let src = |x| {
+            format!(
+                "switch {} {{ case 1: y := 10 case 2: y := 20 default: y := 0 }} z := y",
+                x
+            )
+        };
+        assert_eq!(interpret(&src(2)), Ok(ints(&[20])));
+        assert_eq!(interpret(&src(1)), Ok(ints(&[10])));
+        assert_eq!(interpret(&src(7)), Ok(ints(&[0])));
+        assert_eq!(
+            interpret("y := 5 switch 3 { case 1: y := 1 } z := y"),
+            Ok(ints(&[5, 5]))
+        );
+        assert_eq!(
+            interpret("switch 1 { case 1: goto done default: x := 1 } x := 2 label done: y := 3"),
+            Ok(ints(&[3]))
+        );
+    }
+
+    #[test]
+    fn interpret_output_limit() {
+        let output = Output::default();
+        let stmts = Parser::new(Scanner::new("i := 0 while 1 do print i i := i + 1 end")).collect();
+        let mut interpreter = Interpreter::with_output_limit(stmts, 3);
+        interpreter.writer = Box::new(output.clone());
+        assert_eq!(
+            interpreter.visit(),
+            Err(RuntimeError::OutputLimitExceeded(3))
+        );
+        assert_eq!(output.contents(), "0\n1\n2\n");
+
+        let stmts = Parser::new(Scanner::new("print 1 print 2")).collect();
+        let mut interpreter = Interpreter::with_output_limit(stmts, 2);
+        interpreter.writer = Box::new(Output::default());
+        assert_eq!(interpreter.visit(), Ok(ints(&[1, 2])));
+    }
+
+    #[test]
+    fn interpret_while_step_limit() {
+        let stmts = Parser::new(Scanner::new("while 1 do end")).collect();
+        let res = Interpreter::with_step_limit(stmts, 100).visit();
+        assert_eq!(res, Err(RuntimeError::StepLimitExceeded(100)));
+    }
+
+    #[test]
+    fn interpret_chained_options() {
+        let stmts =
+            Parser::new(Scanner::new("x := get_input(stdin) + 250 while 1 do end")).collect();
+        let res = Interpreter::new(stmts)
+            .inputs(vec![10])
+            .word_width(WordWidth::U8)
+            .overflow_mode(OverflowMode::Checked)
+            .step_limit(100)
+            .visit();
+        assert_eq!(res, Err(RuntimeError::Overflow { line: 1 }));
+    }
+
+    #[test]
+    fn interpret_if_then_else() {
+        let program = |x| {
+            format!(
+                "x := {} if x then goto 2 else goto 4 y := 10 goto 5 y := 20 print y",
+                x
+            )
+        };
+        let output = Output::default();
+        let stmts = Parser::new(Scanner::new(&program(1))).collect();
+        let res = Interpreter::with_writer(stmts, output.clone()).visit();
+        assert_eq!(res, Ok(ints(&[1, 10, 10])));
+
+        let stmts = Parser::new(Scanner::new(&program(0))).collect();
+        let res = Interpreter::with_writer(stmts, output.clone()).visit();
+        assert_eq!(res, Ok(ints(&[0, 20, 20])));
+        assert_eq!(output.contents(), "10\n20\n");
+    }
+
+    #[test]
+    fn interpret_invalid_jump_target() {
+        assert_eq!(
+            interpret("if 1 then goto 9 else goto 0"),
+            Err(RuntimeError::InvalidJumpTarget(9))
+        );
+        assert_eq!(interpret("goto 1"), Ok(vec![]));
+    }
+
+    #[test]
+    fn interpret_assert_comparison() {
+        let err = interpret("x := 2\nassert x == 3").unwrap_err();
+        assert_eq!(
+            err,
+            RuntimeError::ComparisonFailed {
+                line: 2,
+                op: "==".into(),
+                lhs: Value::Int(2),
+                rhs: Value::Int(3),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "assertion failed at line 2 (2 == 3 is false)"
+        );
+        assert_eq!(
+            interpret("assert 5").unwrap_err().to_string(),
+            "assertion failed at line 1 (evaluated to 5)"
+        );
+    }
+
+    #[test]
+    fn interpret_booleans() {
+        assert_eq!(interpret("assert true"), Ok(vec![]));
+        assert_eq!(
+            interpret("assert false"),
+            Err(RuntimeError::AssertionFailed {
+                line: 1,
+                value: Value::Int(0)
+            })
+        );
+        assert_eq!(interpret("x := (1 < 2) == true"), Ok(ints(&[1])));
+    }
+
+    #[test]
+    fn interpret_non_boolean_condition() {
+        assert_eq!(
+            interpret("if 5 then goto 1 else goto 2 x := 1 y := 2"),
+            Err(RuntimeError::NonBooleanCondition(Value::Int(5)))
+        );
+        assert_eq!(
+            interpret("if 2 > 1 then goto 1 else goto 0 x := 1"),
+            Ok(ints(&[1]))
+        );
+    }
+
+    #[test]
+    fn interpret_goto_undefined_label() {
+        assert_eq!(
+            interpret("goto nowhere"),
+            Err(RuntimeError::UndefinedLabel("nowhere".into()))
+        );
+    }
+
+    #[test]
+    fn interpret_print() {
+        let output = Output::default();
+        let stmts = Parser::new(Scanner::new("print 2 + 3 print 7")).collect();
+        let res = Interpreter::with_writer(stmts, output.clone()).visit();
+        assert_eq!(res, Ok(ints(&[5, 7])));
+        assert_eq!(output.contents(), "5\n7\n");
+    }
+
+    #[test]
+    fn interpret_dump() {
+        let output = Output::default();
+        let stmts = Parser::new(Scanner::new("x := 7 dump store(2, 6) y := \"a\" dump")).collect();
+        let res = Interpreter::with_writer(stmts, output.clone()).visit();
+        assert_eq!(res, Ok(vec![Value::Int(7), Value::Str("a".into())]));
+        assert_eq!(
+            output.contents(),
+            "vars: x=7\nregisters: \nvars: x=7, y=a\nregisters: 2=6\n"
+        );
+    }
+
+    #[test]
+    fn interpret_print_format() {
+        let print = |src, format| {
+            let output = Output::default();
+            let stmts = Parser::new(Scanner::new(src)).collect();
+            Interpreter::with_writer(stmts, output.clone())
+                .output_format(format)
+                .visit()
+                .unwrap();
+            output.contents()
+        };
+        assert_eq!(print("print 255", OutputFormat::Hex), "0xff\n");
+        assert_eq!(
+            print("print 5 print [1, 2]", OutputFormat::Bin),
+            "0b101\n[0b1, 0b10]\n"
+        );
+        assert_eq!(
+            print("print 1.5 print \"a\"", OutputFormat::Hex),
+            "1.5\na\n"
+        );
+    }
+
+    #[test]
+    fn interpret_get_input() {
+        let get_input =
+            || StmtKind::Print(Box::new(ExprKind::GetInput("stdin".into(), None).into())).into();
+        let stmts = vec![get_input(), get_input(), get_input()];
+        let res = Interpreter::with_input(stmts, io::Cursor::new("10\n20")).visit();
+        assert_eq!(res, Err(RuntimeError::InputExhausted));
+
+        let stmts = vec![get_input(), get_input()];
+        let output = Output::default();
+        let mut interpreter = Interpreter::with_input(stmts, io::Cursor::new("10\n20"));
+        interpreter.writer = Box::new(output.clone());
+        assert_eq!(interpreter.visit(), Ok(ints(&[10, 20])));
+        assert_eq!(output.contents(), "10\n20\n");
+    }
+
+    #[test]
+    fn interpret_queued_inputs() {
+        let get_input =
+            || StmtKind::Print(Box::new(ExprKind::GetInput("stdin".into(), None).into())).into();
+        let output = Output::default();
+        let mut interpreter = Interpreter::with_inputs(vec![get_input(), get_input()], vec![7, 9]);
+        interpreter.writer = Box::new(output.clone());
+        assert_eq!(interpreter.run(), Ok(ints(&[7, 9])));
+        assert_eq!(interpreter.inputs, Some(VecDeque::new()));
+        assert_eq!(output.contents(), "7\n9\n");
+
+        let res = Interpreter::with_inputs(vec![get_input()], vec![]).visit();
+        assert_eq!(res, Err(RuntimeError::InputExhausted));
+    }
+
+    #[test]
+    fn interpret_get_input_env() {
+        let stmts = || {
+            Parser::new(Scanner::new("x := get_input(env, LIMIT) y := x + 1")).collect::<Vec<_>>()
+        };
+        let env = |value: &str| Map::from([("LIMIT".to_owned(), value.to_owned())]);
+        let res = Interpreter::with_env(stmts(), env(" 41 ")).visit();
+        assert_eq!(res, Ok(ints(&[41, 42])));
+        let res = Interpreter::with_env(stmts(), env("many")).visit();
+        assert_eq!(res, Err(RuntimeError::InvalidInput("many".into())));
+        let res = Interpreter::with_env(stmts(), Map::new()).visit();
+        assert_eq!(res, Err(RuntimeError::UndefinedEnvVar("LIMIT".into())));
+    }
+
+    #[test]
+    fn interpret_get_input_argv() {
+        let stmts =
+            Parser::new(Scanner::new("x := get_input(argv) y := get_input(argv)")).collect();
+        let mut interpreter = Interpreter::new(stmts).args(vec!["3".into(), "two".into()]);
+        assert_eq!(
+            interpreter.run(),
+            Ok(vec![Value::Int(3), Value::Str("two".into())])
+        );
+
+        assert_eq!(
+            interpret("x := get_input(argv)"),
+            Err(RuntimeError::InputExhausted)
+        );
+    }
+
+    #[test]
+    fn interpret_text_input() {
+        let stmts =
+            vec![StmtKind::Print(Box::new(ExprKind::GetInput("stdin".into(), None).into())).into()];
+        let res = Interpreter::with_input(stmts, io::Cursor::new("ten")).visit();
+        assert_eq!(res, Ok(vec![Value::Str("ten".into())]));
+    }
+
+    #[test]
+    fn interpret_input_line() {
+        let run = |src, input: &'static str| {
+            let stmts = Parser::new(Scanner::new(src)).collect();
+            Interpreter::with_input(stmts, io::Cursor::new(input)).visit()
+        };
+        assert_eq!(
+            run("x := get_input_line() y := get_input_line()", "3 4 5\n 6\n"),
+            Ok(vec![Value::Array(vec![3, 4, 5]), Value::Array(vec![6])])
+        );
+        assert_eq!(
+            run("x := get_input(stdin) y := get_input_line()", "1 2 3"),
+            Ok(vec![Value::Int(1), Value::Array(vec![2, 3])])
+        );
+        assert_eq!(
+            run("x := get_input_line()", "1 two 3\n"),
+            Err(RuntimeError::InvalidInput("two".into()))
+        );
+        assert_eq!(
+            run("x := get_input_line()", ""),
+            Err(RuntimeError::InputExhausted)
+        );
+
+        let stmts = Parser::new(Scanner::new("x := get_input_line()")).collect();
+        let res = Interpreter::with_inputs(stmts, vec![3, 4, 5]).visit();
+        assert_eq!(res, Ok(vec![Value::Array(vec![3, 4, 5])]));
+    }
+
+    #[test]
+    fn interpret_wrapping_memory() {
+        let stmts = Parser::new(Scanner::new("store(8 + 3, 42) x := load(3)")).collect();
+        let res = Interpreter::with_wrapping_memory(stmts, 8).visit();
+        assert_eq!(res, Ok(ints(&[42])));
+    }
+
+    #[test]
+    fn interpret_memory_size() {
+        let stmts = Parser::new(Scanner::new("store(7, 3) x := load(7) y := load(0)")).collect();
+        let res = Interpreter::with_memory_size(stmts, 8).visit();
+        assert_eq!(res, Ok(ints(&[3, 0])));
+
+        let stmts = Parser::new(Scanner::new("store(8, 3)")).collect();
+        let res = Interpreter::with_memory_size(stmts, 8).visit();
+        assert_eq!(res, Err(RuntimeError::OutOfBounds(8)));
+    }
+
+    #[test]
+    fn interpret_undefined_register() {
+        assert_eq!(
+            interpret("x := load(7)"),
+            Err(RuntimeError::UndefinedRegister(7))
+        );
+    }
+
+    #[test]
+    fn interpret_division_by_zero() {
+        assert_eq!(
+            interpret("x := 1 / 0"),
+            Err(RuntimeError::DivisionByZero { line: 1 })
+        );
+    }
+
+    #[test]
+    fn interpret_logical() {
+        assert_eq!(
+            interpret("a := 1 and 2 b := 1 and 0 c := 0 or 3 d := 0 or 0 e := not 0 f := !7"),
+            Ok(ints(&[1, 0, 1, 0, 1, 0]))
+        );
+    }
+
+    #[test]
+    fn interpret_logical_short_circuit() {
+        assert_eq!(interpret("x := 0 and 1 / 0"), Ok(ints(&[0])));
+        assert_eq!(interpret("x := 1 or 1 / 0"), Ok(ints(&[1])));
+        assert_eq!(
+            interpret("x := 1 and 1 / 0"),
+            Err(RuntimeError::DivisionByZero { line: 1 })
+        );
+    }
+
+    #[test]
+    fn interpret_conditional() {
+        assert_eq!(interpret("x := 1 ? 10 : 20"), Ok(ints(&[10])));
+        assert_eq!(interpret("x := 0 ? 10 : 20"), Ok(ints(&[20])));
+        assert_eq!(interpret("x := 1 ? 10 : 1 / 0"), Ok(ints(&[10])));
+        assert_eq!(interpret("x := 0 ? 1 / 0 : 20"), Ok(ints(&[20])));
+        assert_eq!(interpret(r#"x := "" ? 1 : 0 ? 2 : 3"#), Ok(ints(&[3])));
+    }
+
+    #[test]
+    fn interpret_literal_too_wide() {
+        let run = |src| {
+            let stmts = Parser::new(Scanner::new(src)).collect();
+            Interpreter::with_word_width(stmts, WordWidth::U8).visit()
+        };
+        assert_eq!(run("print 255"), Ok(ints(&[255])));
+        assert_eq!(
+            run("print 300"),
+            Err(RuntimeError::LiteralTooWide {
+                line: 1,
+                value: 300
+            })
+        );
+        assert_eq!(
+            run("x := 1\ny := 300"),
+            Err(RuntimeError::LiteralTooWide {
+                line: 2,
+                value: 300
+            })
+        );
+    }
+
+    #[test]
+    fn interpret_rand() {
+        let src = "a := rand() b := rand() c := rand()";
+        let parse = || Parser::new(Scanner::new(src)).collect::<Vec<_>>();
+        let run = |seed| Interpreter::with_seed(parse(), seed).visit().unwrap();
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+        assert_ne!(run(42)[0], run(42)[1]);
+        assert_eq!(interpret(src), interpret(src));
+
+        let narrow = Interpreter::with_word_width(parse(), WordWidth::U8).visit();
+        assert!(narrow.unwrap().iter().all(|v| v.as_int().unwrap() <= 255));
+    }
+
+    #[test]
+    fn interpret_steps_and_pc() {
+        assert_eq!(
+            interpret("a := steps() b := steps() c := steps()"),
+            Ok(ints(&[1, 2, 3]))
+        );
+        assert_eq!(
+            interpret("a := steps() while steps() < 5 do nop end b := steps()"),
+            Ok(ints(&[1, 6]))
+        );
+        assert_eq!(
+            interpret("x := pc() nop y := pc() { z := pc() } label l: w := pc()"),
+            Ok(ints(&[0, 2, 5]))
+        );
+        assert_eq!(
+            interpret("x := 0 x := x + 1 y := pc() if x < 3 then goto 1 else nop"),
+            Ok(ints(&[0, 1, 2, 2, 2, 3, 2]))
+        );
+    }
+
+    #[test]
+    fn interpret_builtins() {
+        assert_eq!(interpret("x := max(3, 7)"), Ok(ints(&[7])));
+        assert_eq!(interpret("x := min(3, 7)"), Ok(ints(&[3])));
+        assert_eq!(interpret("x := abs(5)"), Ok(ints(&[5])));
+        assert_eq!(
+            interpret("x := max(3, 2.5) y := abs(0.0 - 1.5)"),
+            Ok(vec![Value::Float(3.0), Value::Float(1.5)])
+        );
+        assert_eq!(
+            interpret(r#"x := min("a", 1)"#),
+            Err(RuntimeError::ExpectedNumber(Value::Str("a".into())))
+        );
+    }
+
+    #[test]
+    fn interpret_bit_builtins() {
+        assert_eq!(interpret("x := isqrt(16)"), Ok(ints(&[4])));
+        assert_eq!(interpret("x := isqrt(17) y := isqrt(0)"), Ok(ints(&[4, 0])));
+        assert_eq!(interpret("x := popcount(7)"), Ok(ints(&[3])));
+        assert_eq!(interpret("x := clz(1) y := ctz(8)"), Ok(ints(&[31, 3])));
+        assert_eq!(interpret("x := clz(0) y := ctz(0)"), Ok(ints(&[32, 32])));
+        assert_eq!(
+            interpret("x := isqrt(2.0)"),
+            Err(RuntimeError::ExpectedInt(Value::Float(2.0)))
+        );
+
+        let stmts = Parser::new(Scanner::new("x := clz(1) y := ctz(0)")).collect();
+        let res = Interpreter::with_word_width(stmts, WordWidth::U8).visit();
+        assert_eq!(res, Ok(ints(&[7, 8])));
+    }
+
+    #[test]
+    fn interpret_named_registers() {
+        assert_eq!(interpret("store(@r0, 5) x := load(@r0)"), Ok(ints(&[5])));
+        assert_eq!(
+            interpret("store8(@r0, 300) x := load(@r0)"),
+            Ok(ints(&[44]))
+        );
+        assert_eq!(
+            interpret("x := load(@r0)"),
+            Err(RuntimeError::UndefinedNamedRegister("r0".into()))
+        );
+
+        let stmts = Parser::new(Scanner::new(
+            "store(@a, 1) store(@b, 2) store(0, 3) store(@a, 4)",
+        ));
+        let outcome = Interpreter::new(stmts.collect())
+            .run_to_completion()
+            .unwrap();
+        assert_eq!(outcome.registers, BTreeMap::from([(0, 3)]));
+        assert_eq!(
+            outcome.named_registers,
+            BTreeMap::from([("a".into(), 4), ("b".into(), 2)])
+        );
+    }
+
+    #[test]
+    fn interpret_named_registers_apart_from_memory() {
+        assert_eq!(
+            interpret("store(@a, 1) store(4294967295, 9) y := load(@a)"),
+            Ok(ints(&[1]))
+        );
+
+        let stmts = Parser::new(Scanner::new(
+            "store(@a, 1) store(@b, 2) store(@c, 3) x := load(@a)",
+        ))
+        .collect();
+        let res = Interpreter::with_wrapping_memory(stmts, 2).visit();
+        assert_eq!(res, Ok(ints(&[1])));
+    }
+
+    #[test]
+    fn interpret_sized_memory_access() {
+        assert_eq!(interpret("store8(0, 300) x := load8(0)"), Ok(ints(&[44])));
+        assert_eq!(
+            interpret("store(0, 70000) x := load16(0) y := load8(0) z := load(0)"),
+            Ok(ints(&[4464, 112, 70000]))
+        );
+        assert_eq!(interpret("store16(1, 65537) x := load(1)"), Ok(ints(&[1])));
+
+        let stmts = Parser::new(Scanner::new("store8(3, 511) x := load8(3)")).collect();
+        let res = Interpreter::with_memory_size(stmts, 4).visit();
+        assert_eq!(res, Ok(ints(&[255])));
+    }
+
+    #[test]
+    fn interpret_assignment_read_back() {
+        assert_eq!(interpret("x := 5 store(0, x)"), Ok(ints(&[5])));
+    }
+
+    #[test]
+    fn overflow_wrapping() {
+        let mode = OverflowMode::Wrapping;
+        assert_eq!(interpret_with("x := 4294967295 + 1", mode), Ok(ints(&[0])));
+        assert_eq!(interpret_with("x := 0 - 1", mode), Ok(ints(&[u32::MAX])));
+        assert_eq!(
+            interpret_with("x := 4294967295 * 2", mode),
+            Ok(ints(&[u32::MAX - 1]))
+        );
+    }
+
+    #[test]
+    fn overflow_checked() {
+        let mode = OverflowMode::Checked;
+        let err = Err(RuntimeError::Overflow { line: 1 });
+        assert_eq!(interpret_with("x := 4294967295 + 1", mode), err);
+        assert_eq!(interpret_with("x := 0 - 1", mode), err);
+        assert_eq!(interpret_with("x := 4294967295 * 2", mode), err);
+        assert_eq!(
+            interpret_with("x := 4294967294 + 1", mode),
+            Ok(ints(&[u32::MAX]))
+        );
+    }
+
+    /// Interpret the source, returning the messages logged at `INFO` level and above.
+    fn logged(src: &str, mode: OverflowMode) -> String {
+        #[derive(Clone, Default)]
+        struct Log(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+        impl Write for Log {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let log = Log::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::WARN)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _ = tracing::subscriber::with_default(subscriber, || interpret_with(src, mode));
+        let bytes = log.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn underflow_checked() {
+        assert_eq!(
+            interpret_with("x := 3 - 5", OverflowMode::Checked),
+            Err(RuntimeError::Overflow { line: 1 })
+        );
+        assert_eq!(
+            interpret_with("x := 3 - 5", OverflowMode::Wrapping),
+            Ok(ints(&[4294967294]))
+        );
+        assert_eq!(
+            interpret_with("if 3 - 5 then goto 2 else goto 2", OverflowMode::Wrapping),
+            Err(RuntimeError::NonBooleanCondition(Value::Int(4294967294)))
+        );
+        assert_eq!(
+            interpret_with("assert 3 - 5", OverflowMode::Wrapping),
+            Err(RuntimeError::AssertionFailed {
+                line: 1,
+                value: Value::Int(4294967294)
+            })
+        );
+
+        let wraps = logged("x := 3 - 5", OverflowMode::Wrapping);
+        assert!(wraps.contains("WARN"));
+        assert!(wraps.contains("3 - 5 underflows and wraps around"));
+        assert!(!logged("x := 5 - 3", OverflowMode::Wrapping).contains("underflows"));
+        assert!(!logged("x := 3 - 5", OverflowMode::Checked).contains("underflows"));
+    }
+
+    #[test]
+    fn overflow_saturating() {
+        let mode = OverflowMode::Saturating;
+        assert_eq!(
+            interpret_with("x := 4294967295 + 1", mode),
+            Ok(ints(&[u32::MAX]))
+        );
+        assert_eq!(interpret_with("x := 0 - 1", mode), Ok(ints(&[0])));
+        assert_eq!(
+            interpret_with("x := 4294967295 * 2", mode),
+            Ok(ints(&[u32::MAX]))
+        );
+    }
+
+    #[test]
+    fn interpret_unary() {
+        assert_eq!(interpret("x := -1 + 2"), Ok(ints(&[1])));
+        assert_eq!(interpret("x := -1"), Ok(ints(&[u32::MAX])));
+        assert_eq!(interpret("x := +3"), Ok(ints(&[3])));
+        assert_eq!(interpret("x := 5 y := -x + 7"), Ok(ints(&[5, 2])));
+    }
+
+    #[test]
+    fn interpret_unary_overflow_modes() {
+        let err = Err(RuntimeError::Overflow { line: 1 });
+        assert_eq!(interpret_with("x := -1", OverflowMode::Checked), err);
+        assert_eq!(
+            interpret_with("x := -0", OverflowMode::Checked),
+            Ok(ints(&[0]))
+        );
+        assert_eq!(
+            interpret_with("x := -1", OverflowMode::Saturating),
+            Ok(ints(&[0]))
+        );
+    }
 }
@@ -0,0 +1,72 @@
+//! A Rust implementation of a simpIL interpreter.
+//!
+//! Source code flows through the [`Scanner`], the [`Parser`] and finally the [`Interpreter`].
+//! [`run_source`] does all three at once.
+
+/// Static checks over a parsed program.
+pub mod analysis;
+/// Turn a syntax tree into instructions for the `Vm`.
+pub mod compile;
+/// Render a program with the index of each statement.
+pub mod disasm;
+/// Traverse and execute a syntax tree.
+pub mod interpreter;
+/// The memory models used by the interpreter.
+pub mod memory;
+/// Rewrite a syntax tree into an equivalent, faster one.
+pub mod optimize;
+/// Turn a token iterator into a statement iterator.
+pub mod parser;
+/// Turn a syntax tree back into source code.
+pub mod printer;
+/// Turn a string into a token iterator.
+pub mod scanner;
+/// Definitions of the simpIL syntax.
+pub mod syntax;
+/// Definitions of the simpIL tokens.
+pub mod tokens;
+/// Runtime values, i.e. integers, floats and strings.
+pub mod value;
+/// A common interface for traversing the syntax tree.
+pub mod visitor;
+/// Execute compiled instructions over a stack.
+pub mod vm;
+
+pub use interpreter::Interpreter;
+pub use parser::Parser;
+pub use scanner::Scanner;
+pub use syntax::{Expr, ExprKind, Span, Stmt, StmtKind};
+pub use value::Value;
+
+#[doc(hidden)]
+pub type Error = Box<dyn std::error::Error>;
+
+#[doc(hidden)]
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Scan and parse the source code into statements.
+///
+/// Fails on the first error rather than skipping the statements which do not parse,
+/// see [`Parser::into_program`].
+pub fn parse(code: &str) -> Result<Vec<Stmt>> {
+    Ok(Parser::new(Scanner::new(code)).into_program()?)
+}
+
+/// Scan and parse the source code, collecting every error rather than skipping statements.
+///
+/// It terminates without panicking on any input, which makes it suitable for fuzzing.
+pub fn parse_str(code: &str) -> std::result::Result<Vec<Stmt>, Vec<parser::ParseError>> {
+    let (statements, errors) = Parser::new(Scanner::new(code)).parse_all();
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Scan, parse and run the source code.
+///
+/// Returns the values of the executed assignments, prints and expressions.
+pub fn run_source(code: &str) -> Result<Vec<Value>> {
+    Ok(Interpreter::new(parse(code)?).visit()?)
+}
@@ -1,96 +1,755 @@
-//! A Rust implementation of a simpIL interpreter.
+//! The command line interface of simpilrs.
 
-use argh::FromArgs;
-use parser::Parser;
-use scanner::Scanner;
+use argh::{EarlyExit, FromArgs};
+use simpilrs::analysis::{
+    bad_jump_targets, undefined_variables, unreachable_statements, AnalysisError,
+};
+use simpilrs::compile::compile;
+use simpilrs::disasm::disassemble;
+use simpilrs::interpreter::RuntimeError;
+use simpilrs::memory::Memory;
+use simpilrs::parser::ParseError;
+use simpilrs::scanner::ScanError;
+use simpilrs::value::OutputFormat;
+use simpilrs::vm::Vm;
+use simpilrs::{parse, Interpreter, Parser, Result, Scanner, Span, Stmt, StmtKind, Value};
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-
-use interpreter::Interpreter;
-use io::BufReader;
+use std::process::ExitCode;
+use tracing::{level_filters::LevelFilter, Level};
 use tracing_subscriber as tsub;
 
-/// Traverse and execute a syntax tree.
-mod interpreter;
-/// Turn a token iterator into a statement iterator.
-mod parser;
-/// Turn a string into a token iterator.
-mod scanner;
-/// Definitions of the simpIL syntax.
-mod syntax;
-/// Definitions of the simpIL tokens.
-mod tokens;
-
-#[doc(hidden)]
-pub(crate) type Error = Box<dyn std::error::Error>;
-
-#[doc(hidden)]
-pub(crate) type Result<T> = std::result::Result<T, Error>;
-
 /// Run simpilrs on a simpIL script.
-#[derive(FromArgs)]
+#[derive(FromArgs, Default)]
 struct CommandStruct {
     #[argh(positional)]
     file_name: Option<String>,
+
+    /// print the syntax tree as JSON instead of running the program
+    #[argh(switch)]
+    dump_ast: bool,
+
+    /// print the scanned tokens instead of running the program
+    #[argh(switch)]
+    tokens: bool,
+
+    /// print the numbered statements instead of running the program
+    #[argh(switch)]
+    disasm: bool,
+
+    /// analyze the program and report any problems instead of running it
+    #[argh(switch)]
+    check: bool,
+
+    /// print the tokens, the statements and each statement before it is executed
+    #[argh(switch)]
+    trace: bool,
+
+    /// use a memory of this many words, wrapping addresses around its size
+    #[argh(option)]
+    mem_wrap: Option<usize>,
+
+    /// stop the program with an error once it prints more than this many values
+    #[argh(option)]
+    max_output: Option<usize>,
+
+    /// run this program text instead of a file and print its final value
+    #[argh(option)]
+    eval: Option<String>,
+
+    /// log what the interpreter does, repeat for more detail
+    #[argh(switch, short = 'v')]
+    verbose: u8,
+
+    /// print integers as dec, hex (0x1f) or bin (0b11111)
+    #[argh(option, default = "OutputFormat::Dec")]
+    format: OutputFormat,
+
+    /// an argument for the script, read in order by `get_input(argv)`; may be repeated
+    #[argh(option)]
+    arg: Vec<String>,
 }
 
 /// Run a program from a file, or as an interactive prompt.
-fn main() -> Result<()> {
-    tsub::fmt::init();
-    let cmd: CommandStruct = argh::from_env();
-
-    match cmd.file_name {
-        Some(f) => run_file(f)?,
-        None => run_prompt()?,
+///
+/// Exits with a code telling what went wrong, see `exit_code`.
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let cmd = match parse_args(&args[0], &args[1..]) {
+        Ok(cmd) => cmd,
+        Err(early_exit) if early_exit.status.is_ok() => {
+            println!("{}", early_exit.output);
+            return ExitCode::SUCCESS;
+        }
+        Err(early_exit) => {
+            eprintln!("{}", early_exit.output);
+            return ExitCode::FAILURE;
+        }
     };
+    let filter = tsub::EnvFilter::from_default_env()
+        .add_directive(LevelFilter::from(log_level(cmd.verbose)).into());
+    tsub::fmt().with_env_filter(filter).init();
 
-    Ok(())
+    match dispatch(&cmd) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// The exit code for an error, by the stage which reported it:
+/// `2` for scanning, `3` for parsing, `4` for running and `5` for a failed assertion.
+/// A jump past the end of the program gives `4` whether it is found before running or while.
+/// Any other error, such as an unreadable file or invalid flags, gives `1`.
+fn exit_code(err: &simpilrs::Error) -> u8 {
+    if let Some(AnalysisError::BadJumpTarget { .. }) = err.downcast_ref::<AnalysisError>() {
+        return 4;
+    }
+    if let Some(err) = err.downcast_ref::<RuntimeError>() {
+        return match err {
+            RuntimeError::AssertionFailed { .. } | RuntimeError::ComparisonFailed { .. } => 5,
+            _ => 4,
+        };
+    }
+    match err.downcast_ref::<ParseError>() {
+        Some(ParseError::Scan(_)) => 2,
+        Some(_) => 3,
+        None if err.is::<ScanError>() => 2,
+        None => 1,
+    }
+}
+
+/// Parse the command line arguments, accepting `-vv` for `-v -v`, which argh does not.
+fn parse_args(command: &str, args: &[String]) -> std::result::Result<CommandStruct, EarlyExit> {
+    let args: Vec<&str> = args
+        .iter()
+        .flat_map(|arg| match arg.strip_prefix('-') {
+            Some(flags) if flags.len() > 1 && flags.bytes().all(|flag| flag == b'v') => {
+                vec!["-v"; flags.len()]
+            }
+            _ => vec![arg.as_str()],
+        })
+        .collect();
+    CommandStruct::from_args(&[command], &args)
 }
 
-/// Print the prompt to stdout
-fn prompt() -> io::Result<()> {
-    print!("> ");
+/// The most detailed level logged when `--verbose` is given `verbose` times.
+fn log_level(verbose: u8) -> Level {
+    match verbose {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// Run the program given on the command line, or the interactive prompt if there is none.
+fn dispatch(cmd: &CommandStruct) -> Result<()> {
+    match (&cmd.file_name, &cmd.eval) {
+        (Some(_), Some(_)) => Err("a file and --eval cannot be combined".into()),
+        (Some(f), None) => run_file(f, cmd),
+        (None, Some(code)) => eval(code, cmd, &mut io::stdout()),
+        (None, None) => run_prompt(cmd),
+    }
+}
+
+/// Print the prompt to stdout.
+/// A continuation prompt asks for the rest of an incomplete statement.
+fn prompt(continuation: bool) -> io::Result<()> {
+    print!("{}", if continuation { "... " } else { "> " });
     io::stdout().flush()
 }
 
+/// Add a line to the code buffered so far.
+///
+/// Returns the buffered code once it can be parsed, i.e. no more lines are needed,
+/// and clears the buffer. An empty line ends the code regardless, so its errors are reported.
+fn complete(buffer: &mut String, line: &str) -> Option<String> {
+    buffer.push_str(line);
+    buffer.push('\n');
+    let (_, errors) = Parser::new(Scanner::new(buffer)).parse_all();
+    if line.trim().is_empty() || !errors.iter().any(ParseError::is_incomplete) {
+        Some(std::mem::take(buffer))
+    } else {
+        None
+    }
+}
+
 /// Interactive script mode.
-fn run_prompt() -> Result<()> {
+///
+/// Lines are run by one interpreter, so variables and memory persist between them.
+/// An incomplete statement is continued on the next line, see `complete`.
+/// Lines starting with `:` are meta-commands, see `meta_command`.
+fn run_prompt(cmd: &CommandStruct) -> Result<()> {
     let stdin = std::io::stdin();
-    prompt()?;
+    let mut session = interpreter(vec![], cmd);
+    let mut buffer = String::new();
+    prompt(false)?;
     for line in stdin.lock().lines() {
-        match line {
-            Ok(l) => run(l)?,
-            Err(_) => break,
+        let Ok(l) = line else { break };
+        let result = if buffer.is_empty() && l.trim_start().starts_with(':') {
+            meta_command(&l, &mut session, cmd, &mut io::stdout())
+        } else {
+            match complete(&mut buffer, &l) {
+                None => Ok(true),
+                Some(code) if cmd.tokens || cmd.dump_ast || cmd.disasm || cmd.check => {
+                    process(code, cmd).map(|_| true)
+                }
+                Some(code) => {
+                    execute(&code, &mut session, cmd.format, &mut io::stdout()).map(|_| true)
+                }
+            }
         };
-        prompt()?;
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("{}", e),
+        }
+        prompt(!buffer.is_empty())?;
     }
 
     Ok(())
 }
 
-/// Load script from file.
-fn run_file(file_name: String) -> Result<()> {
-    let reader = BufReader::new(File::open(file_name)?);
-    for line in reader.lines() {
-        let line = line?;
-        run(line)?;
+/// Handle a REPL meta-command, without scanning or parsing it.
+///
+/// - `:vars` prints the variables in scope.
+/// - `:regs` prints the stored registers.
+/// - `:reset` forgets all variables, registers and labels.
+/// - `:quit` exits the prompt.
+///
+/// Returns whether the prompt should keep reading lines.
+fn meta_command(
+    line: &str,
+    session: &mut Interpreter,
+    cmd: &CommandStruct,
+    out: &mut impl Write,
+) -> Result<bool> {
+    match line.trim() {
+        ":vars" => {
+            for (name, value) in session.vars() {
+                writeln!(out, "{} = {}", name, value)?;
+            }
+        }
+        ":regs" => {
+            for (addr, value) in session.registers().entries() {
+                writeln!(out, "[{}] = {}", addr, value)?;
+            }
+            for (name, value) in session.named_registers() {
+                writeln!(out, "[@{}] = {}", name, value)?;
+            }
+        }
+        ":reset" => *session = interpreter(vec![], cmd),
+        ":quit" => return Ok(false),
+        other => return Err(format!("Unknown command '{}'", other).into()),
+    }
+    Ok(true)
+}
+
+/// Run a line of source code in the REPL session.
+/// If the line ends with an expression or an assignment, its value is written to `out`.
+fn execute(
+    code: &str,
+    session: &mut Interpreter,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<Vec<Value>> {
+    let statements = parse(code)?;
+    let echo = echoes(&statements);
+    let results = session.execute(statements)?;
+    if let (true, Some(value)) = (echo, results.last()) {
+        writeln!(out, "{}", value.render(format))?;
+    }
+    Ok(results)
+}
+
+/// Whether the value of the last statement is written after running it,
+/// i.e. it is an expression or an assignment.
+fn echoes(statements: &[Stmt]) -> bool {
+    matches!(
+        statements.last().map(|stmt| &stmt.kind),
+        Some(StmtKind::Expression(_) | StmtKind::Assignment(..) | StmtKind::ConstAssignment(..))
+    )
+}
+
+/// Run the program given with `--eval`, writing its final value to `out` as the prompt does.
+/// Flags which inspect the program instead of running it are handled as for a file.
+fn eval(code: &str, cmd: &CommandStruct, out: &mut impl Write) -> Result<()> {
+    if cmd.tokens || cmd.dump_ast || cmd.disasm || cmd.check {
+        return process(code.to_owned(), cmd);
+    }
+    let mut echo = false;
+    let results = run_with(code.to_owned(), cmd, |stmts| {
+        echo = echoes(&stmts);
+        execute_compiled(stmts, cmd)
+    })?;
+    if let (true, Some(value)) = (echo, results.last()) {
+        writeln!(out, "{}", value.render(cmd.format))?;
     }
     Ok(())
 }
 
-/// Run the whole pipeline, including the interpreter.
-fn run(code: String) -> Result<()> {
-    let scanner = Scanner::new(&code);
-    println!("{}", &scanner);
-    let parser = Parser::new(scanner);
-    println!("{}", &parser);
-    let _ = Interpreter::new(parser);
+/// Render the results of a run as a list, with integers in `format`.
+fn render(results: &[Value], format: OutputFormat) -> String {
+    let rendered: Vec<_> = results.iter().map(|value| value.render(format)).collect();
+    format!("[{}]", rendered.join(", "))
+}
+
+/// Construct an interpreter according to the command line flags.
+fn interpreter(statements: Vec<Stmt>, cmd: &CommandStruct) -> Interpreter {
+    let mut interpreter = Interpreter::new(statements)
+        .args(cmd.arg.clone())
+        .output_format(cmd.format);
+    if cmd.trace {
+        interpreter = interpreter.trace(io::stdout());
+    }
+    if let Some(size) = cmd.mem_wrap {
+        interpreter = interpreter.memory(Memory::wrapping(size));
+    }
+    if let Some(limit) = cmd.max_output {
+        interpreter = interpreter.output_limit(limit);
+    }
+    interpreter
+}
 
+/// Load script from file and process it as a single program.
+fn run_file(file_name: &str, cmd: &CommandStruct) -> Result<()> {
+    let mut source = String::new();
+    File::open(file_name)?.read_to_string(&mut source)?;
+    process(source, cmd)
+}
+
+/// Handle a piece of source code according to the command line flags.
+fn process(code: String, cmd: &CommandStruct) -> Result<()> {
+    if cmd.tokens {
+        print_tokens(&code, &mut io::stdout())?;
+    } else if cmd.dump_ast {
+        println!("{}", dump_ast(&code)?);
+    } else if cmd.disasm {
+        print!("{}", disassemble(&parse(&code)?));
+    } else if cmd.check {
+        check(&code, &mut io::stdout())?;
+    } else {
+        run_with(code, cmd, |stmts| execute_compiled(stmts, cmd))?;
+    }
     Ok(())
 }
 
-#[doc(hidden)]
-fn report(line: usize, column: usize, message: &str) {
-    println!("[line {}, column {}] Error {{ {} }}", line, column, message);
+/// Write the tokens of the source code to `out`, one per line,
+/// with the invalid tokens which were skipped in their place among them.
+fn print_tokens(code: &str, out: &mut impl Write) -> Result<()> {
+    let mut scanner = Scanner::new(code);
+    let mut lines: Vec<_> = scanner
+        .by_ref()
+        .map(|token| {
+            let span = Span::of(&token);
+            let line = format!(
+                "[line {}, column {}] {:?} '{}'",
+                token.line, token.column, token.token_type, token.lexeme
+            );
+            ((span.start_line, span.start_col), line)
+        })
+        .collect();
+    lines.extend(
+        scanner
+            .errors()
+            .iter()
+            .map(|error| (error.position(), error.to_string())),
+    );
+    lines.sort_by_key(|&(position, _)| position);
+    for (_, line) in lines {
+        writeln!(out, "{}", line)?;
+    }
+    match scanner.failure() {
+        Some(err) => Err(err.clone().into()),
+        None => Ok(()),
+    }
+}
+
+/// Write the problems found by every analysis of the source code to `out`, followed by
+/// a count of them. Fails if any is an error rather than a warning, without running anything.
+/// If the source does not parse, every scan and parse error is written instead,
+/// and it fails with the first of them, as `Parser::into_program` would.
+fn check(code: &str, out: &mut impl Write) -> Result<()> {
+    let (statements, parse_errors) = Parser::new(Scanner::new(code)).parse_all();
+    if !parse_errors.is_empty() {
+        for error in &parse_errors {
+            writeln!(out, "error: {}", error)?;
+        }
+        writeln!(out, "{} errors, 0 warnings", parse_errors.len())?;
+        let first = parse_errors
+            .into_iter()
+            .min_by_key(|err| !matches!(err, ParseError::Scan(_)));
+        return Err(first.unwrap().into());
+    }
+    let mut errors = undefined_variables(&statements);
+    errors.extend(bad_jump_targets(&statements));
+    errors.sort();
+    let warnings = unreachable_statements(&statements);
+    for error in &errors {
+        writeln!(out, "error: {}", error)?;
+    }
+    for warning in &warnings {
+        writeln!(out, "warning: {}", warning)?;
+    }
+    writeln!(out, "{} errors, {} warnings", errors.len(), warnings.len())?;
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("check failed with {} errors", errors.len()).into())
+    }
+}
+
+/// Render the syntax tree of the source code as JSON.
+fn dump_ast(code: &str) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&parse(code)?)?)
+}
+
+/// Run the statements on the VM, or on the tree-walking interpreter if they are traced.
+/// Literal jumps past the end of the program are reported before anything runs.
+fn execute_compiled(statements: Vec<Stmt>, cmd: &CommandStruct) -> Result<Vec<Value>> {
+    if let Some(err) = bad_jump_targets(&statements).into_iter().next() {
+        return Err(err.into());
+    }
+    if cmd.trace {
+        return Ok(interpreter(statements, cmd).visit()?);
+    }
+    let ops = compile(&statements);
+    Ok(Vm::with_state(ops, interpreter(vec![], cmd)).run()?)
+}
+
+/// Run the whole pipeline, executing the statements with `execute`.
+/// Only the program writes to the output, unless `--trace` also asks for
+/// the tokens, the statements and the results.
+fn run_with(
+    code: String,
+    cmd: &CommandStruct,
+    execute: impl FnOnce(Vec<Stmt>) -> Result<Vec<Value>>,
+) -> Result<Vec<Value>> {
+    if cmd.trace {
+        let scanner = Scanner::new(&code);
+        println!("{}", &scanner);
+        println!("{}", Parser::new(scanner));
+    }
+    let results = execute(parse(&code)?)?;
+    if cmd.trace {
+        println!("{}", render(&results, cmd.format));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the whole pipeline, including the interpreter.
+    ///
+    /// Returns the values of the executed assignments, prints and expressions.
+    fn run(code: String) -> Result<Vec<Value>> {
+        run_with(code, &CommandStruct::default(), |stmts| {
+            Ok(Interpreter::new(stmts).visit()?)
+        })
+    }
+
+    #[test]
+    fn run_assignment() {
+        assert_eq!(run("x := 1".into()).unwrap(), vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn run_store() {
+        assert_eq!(run("store(1, 2)".into()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn run_division_by_zero() {
+        let err = run("x := 1 / 0".into()).unwrap_err();
+        assert_eq!(err.to_string(), "[line 1] division by zero");
+    }
+
+    #[test]
+    fn run_number_overflow() {
+        let err = run("x := 4294967296".into()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 1, column 6] Invalid number '4294967296'"
+        );
+    }
+
+    #[test]
+    fn run_exit_codes() {
+        let code = |src: &str| exit_code(&run(src.into()).unwrap_err());
+        assert_eq!(code("x := \"abc"), 2);
+        assert_eq!(code("x := := 1"), 3);
+        assert_eq!(code("x := 1 / 0"), 4);
+        assert_eq!(code("assert 2 == 3"), 5);
+        assert_eq!(code("x := 1 goto 50"), 4);
+        let compiled = |trace| {
+            let cmd = CommandStruct {
+                trace,
+                ..CommandStruct::default()
+            };
+            exit_code(&execute_compiled(parse("x := 1 goto 50").unwrap(), &cmd).unwrap_err())
+        };
+        assert_eq!(compiled(false), 4);
+        assert_eq!(compiled(true), 4);
+        assert_eq!(exit_code(&"a file and --eval cannot be combined".into()), 1);
+    }
+
+    #[test]
+    fn run_multiline_file() {
+        let file_name = "files/multiline.simpil";
+        assert!(run_file(file_name, &CommandStruct::default()).is_ok());
+        let code = std::fs::read_to_string(file_name).unwrap();
+        assert_eq!(run(code).unwrap(), vec![Value::Int(2), Value::Int(6)]);
+    }
+
+    #[test]
+    fn check_undefined_variable() {
+        let code = std::fs::read_to_string("files/undefined.simpil").unwrap();
+        let mut out = Vec::new();
+        let err = check(&code, &mut out).unwrap_err();
+        assert_eq!(exit_code(&err), 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "error: [line 3] variable 'z' is never assigned\n\
+             warning: [line 3] unreachable statement\n\
+             1 errors, 1 warnings\n"
+        );
+
+        let mut out = Vec::new();
+        assert!(check("x := 1 print x", &mut out).is_ok());
+        assert_eq!(String::from_utf8(out).unwrap(), "0 errors, 0 warnings\n");
+    }
+
+    #[test]
+    fn check_reports_every_invalid_number() {
+        let mut out = Vec::new();
+        let err = check("x := 1__0 print 2 y := _1", &mut out).unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("error: [line 1, column 6] Invalid number '1__0'\n"));
+        assert!(out.contains("error: [line 1, column 24] Invalid number '_1'\n"));
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        let level = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|&arg| arg.to_owned()).collect();
+            log_level(parse_args("simpilrs", &args).unwrap().verbose)
+        };
+        assert_eq!(level(&[]), Level::WARN);
+        assert_eq!(level(&["-v"]), Level::INFO);
+        assert_eq!(level(&["-vv"]), Level::DEBUG);
+        assert_eq!(level(&["-v", "--verbose"]), Level::DEBUG);
+        assert_eq!(level(&["-v", "-v", "-v", "-v"]), Level::TRACE);
+    }
+
+    #[test]
+    fn eval_without_file() {
+        let cmd = CommandStruct {
+            eval: Some("x := 2 + 2".into()),
+            ..CommandStruct::default()
+        };
+        assert!(dispatch(&cmd).is_ok());
+        let mut out = Vec::new();
+        eval("x := 2 + 2", &cmd, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "4\n");
+        let mut out = Vec::new();
+        eval("store(1, 2)", &cmd, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        let cmd = CommandStruct {
+            eval: Some("x := 1 / 0".into()),
+            ..CommandStruct::default()
+        };
+        assert!(dispatch(&cmd).is_err());
+    }
+
+    #[test]
+    fn eval_with_file() {
+        let cmd = CommandStruct {
+            file_name: Some("files/multiline.simpil".into()),
+            eval: Some("x := 2 + 2".into()),
+            ..CommandStruct::default()
+        };
+        let err = dispatch(&cmd).unwrap_err();
+        assert_eq!(err.to_string(), "a file and --eval cannot be combined");
+    }
+
+    #[test]
+    fn continue_incomplete_statement() {
+        let mut buffer = String::new();
+        assert_eq!(complete(&mut buffer, "store(1,"), None);
+        let code = complete(&mut buffer, "2)").unwrap();
+        assert!(buffer.is_empty());
+        let stmts: Vec<_> = parse(&code)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(stmts, vec!["store(1, 2)"]);
+
+        assert_eq!(complete(&mut buffer, "while 1 do"), None);
+        assert_eq!(complete(&mut buffer, ""), Some("while 1 do\n\n".into()));
+        assert_eq!(complete(&mut buffer, "print )"), Some("print )\n".into()));
+        assert_eq!(complete(&mut buffer, "x := 1 + \\"), None);
+        assert_eq!(complete(&mut buffer, "2"), Some("x := 1 + \\\n2\n".into()));
+    }
+
+    #[test]
+    fn execute_echoes_last_value() {
+        let mut session = interpreter(vec![], &CommandStruct::default());
+        let mut echo = |code| {
+            let mut out = Vec::new();
+            execute(code, &mut session, OutputFormat::Dec, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(echo("3 * 4"), "12\n");
+        assert_eq!(echo("x := 5"), "5\n");
+        assert_eq!(echo("y := x + 1 x * y"), "30\n");
+        assert_eq!(echo("store(1, x)"), "");
+        assert_eq!(echo("x := 1 nop"), "");
+
+        let mut out = Vec::new();
+        let err = execute("x := := 3", &mut session, OutputFormat::Dec, &mut out).unwrap_err();
+        assert!(err.to_string().starts_with("[line 1] near ':='"));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn meta_command_reset() {
+        let cmd = CommandStruct::default();
+        let mut session = interpreter(vec![], &cmd);
+        execute(
+            "x := 1 store(2, 3)",
+            &mut session,
+            OutputFormat::Dec,
+            &mut io::sink(),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        assert!(meta_command(":vars", &mut session, &cmd, &mut out).unwrap());
+        assert!(meta_command(":regs", &mut session, &cmd, &mut out).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "x = 1\n[2] = 3\n");
+
+        assert!(meta_command(":reset", &mut session, &cmd, &mut io::sink()).unwrap());
+        let mut out = Vec::new();
+        meta_command(":vars", &mut session, &cmd, &mut out).unwrap();
+        meta_command(":regs", &mut session, &cmd, &mut out).unwrap();
+        assert!(out.is_empty());
+        assert!(execute("y := x", &mut session, OutputFormat::Dec, &mut io::sink()).is_err());
+
+        assert!(!meta_command(":quit", &mut session, &cmd, &mut out).unwrap());
+        assert!(meta_command(":nope", &mut session, &cmd, &mut out).is_err());
+    }
+
+    #[test]
+    fn print_tokens_store() {
+        let mut out = Vec::new();
+        print_tokens("store(1, 2)", &mut out).unwrap();
+        let expected = "\
+[line 1, column 1] Store 'store'
+[line 1, column 6] LeftParen '('
+[line 1, column 7] Value(1) '1'
+[line 1, column 8] Comma ','
+[line 1, column 10] Value(2) '2'
+[line 1, column 11] RightParen ')'
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn print_tokens_invalid() {
+        let mut out = Vec::new();
+        print_tokens("x # 1", &mut out).unwrap();
+        let expected = "\
+[line 1, column 1] Identifier(\"x\") 'x'
+[line 1, column 3] Invalid Token '#'
+[line 1, column 5] Value(1) '1'
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn dump_ast_assignment() {
+        let actual: serde_json::Value =
+            serde_json::from_str(&dump_ast("x := 1 + 2").unwrap()).unwrap();
+        let expected = serde_json::json!([{
+            "Assignment": [
+                { "token_type": { "Identifier": "x" }, "lexeme": "x", "line": 1, "column": 1 },
+                {
+                    "Binary": [
+                        { "Val": 1 },
+                        { "token_type": "Plus", "lexeme": "+", "line": 1, "column": 8 },
+                        { "Val": 2 }
+                    ]
+                }
+            ]
+        }]);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn dump_ast_round_trips() {
+        use simpilrs::tokens::{Token, TokenType};
+        use simpilrs::ExprKind;
+
+        let token = |token_type, lexeme: &str, column| Token {
+            token_type,
+            lexeme: lexeme.into(),
+            line: 1,
+            column,
+        };
+        let actual: Vec<Stmt> = serde_json::from_str(&dump_ast("x := 1 + 2").unwrap()).unwrap();
+        let expected: Vec<Stmt> = vec![StmtKind::Assignment(
+            token(TokenType::Identifier("x".into()), "x", 1),
+            Box::new(
+                ExprKind::Binary(
+                    Box::new(ExprKind::Val(1).into()),
+                    token(TokenType::Plus, "+", 8),
+                    Box::new(ExprKind::Val(2).into()),
+                )
+                .into(),
+            ),
+        )
+        .into()];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn dump_ast_rejects_parse_errors() {
+        let err = dump_ast("x := := 1 print 2").unwrap_err();
+        assert_eq!(exit_code(&err), 3);
+    }
+
+    #[test]
+    fn disasm_rejects_parse_errors() {
+        let cmd = CommandStruct {
+            disasm: true,
+            eval: Some("print ) print 2".into()),
+            ..CommandStruct::default()
+        };
+        assert_eq!(exit_code(&dispatch(&cmd).unwrap_err()), 3);
+    }
+
+    #[test]
+    fn interpreter_combines_flags() {
+        let cmd = CommandStruct {
+            trace: true,
+            mem_wrap: Some(4),
+            max_output: Some(1),
+            ..CommandStruct::default()
+        };
+        let run = |src| interpreter(parse(src).unwrap(), &cmd).visit();
+        assert_eq!(run("store(5, 7) print load(1)"), Ok(vec![Value::Int(7)]));
+        assert_eq!(
+            run("print 1 print 2"),
+            Err(RuntimeError::OutputLimitExceeded(1))
+        );
+    }
 }
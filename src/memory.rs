@@ -0,0 +1,145 @@
+use crate::interpreter::RuntimeError;
+use std::collections::HashMap as Map;
+
+/// The memory addressed by `store` and `load`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Memory {
+    /// Unbounded memory, where only stored addresses take up space.
+    /// Loading an address which was never stored is an error.
+    Sparse(Map<u32, u32>),
+    /// Fixed-size memory, initialized to zero.
+    /// Addresses beyond its size are out of bounds.
+    Linear(Vec<u32>),
+    /// Fixed-size memory, initialized to zero.
+    /// Addresses wrap around modulo its size.
+    Wrapping(Vec<u32>),
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::Sparse(Map::new())
+    }
+}
+
+impl Memory {
+    /// Create a fixed-size memory of `size` words.
+    pub fn linear(size: usize) -> Self {
+        Memory::Linear(vec![0; size])
+    }
+
+    /// Create a fixed-size memory of `size` words, whose addresses wrap around.
+    pub fn wrapping(size: usize) -> Self {
+        Memory::Wrapping(vec![0; size])
+    }
+
+    /// The stored addresses and their values, in order of address.
+    /// Fixed-size memory is zero-initialized, so only nonzero words are included.
+    pub fn entries(&self) -> Vec<(u32, u32)> {
+        let mut entries: Vec<_> = match self {
+            Memory::Sparse(map) => map.iter().map(|(&addr, &val)| (addr, val)).collect(),
+            Memory::Linear(vec) | Memory::Wrapping(vec) => (0..)
+                .zip(vec.iter().copied())
+                .filter(|&(_, val)| val != 0)
+                .collect(),
+        };
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Forget every stored value, keeping the size of fixed-size memory.
+    pub fn clear(&mut self) {
+        match self {
+            Memory::Sparse(map) => map.clear(),
+            Memory::Linear(vec) | Memory::Wrapping(vec) => vec.fill(0),
+        }
+    }
+
+    /// Store `val` at `addr`.
+    pub fn store(&mut self, addr: u32, val: u32) -> Result<(), RuntimeError> {
+        match self {
+            Memory::Sparse(map) => {
+                map.insert(addr, val);
+            }
+            Memory::Linear(vec) => match vec.get_mut(addr as usize) {
+                Some(slot) => *slot = val,
+                None => return Err(RuntimeError::OutOfBounds(addr)),
+            },
+            Memory::Wrapping(vec) => match wrap(vec.len(), addr) {
+                Some(i) => vec[i] = val,
+                None => return Err(RuntimeError::OutOfBounds(addr)),
+            },
+        }
+        Ok(())
+    }
+
+    /// Load the value at `addr`.
+    pub fn load(&self, addr: u32) -> Result<u32, RuntimeError> {
+        match self {
+            Memory::Sparse(map) => map
+                .get(&addr)
+                .copied()
+                .ok_or(RuntimeError::UndefinedRegister(addr)),
+            Memory::Linear(vec) => vec
+                .get(addr as usize)
+                .copied()
+                .ok_or(RuntimeError::OutOfBounds(addr)),
+            Memory::Wrapping(vec) => wrap(vec.len(), addr)
+                .map(|i| vec[i])
+                .ok_or(RuntimeError::OutOfBounds(addr)),
+        }
+    }
+}
+
+/// The index of `addr` in a wrapping memory of `len` words, unless it is empty.
+fn wrap(len: usize, addr: u32) -> Option<usize> {
+    (addr as usize).checked_rem(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_store_load() {
+        let mut memory = Memory::default();
+        assert_eq!(
+            memory.load(4_000_000_000),
+            Err(RuntimeError::UndefinedRegister(4_000_000_000))
+        );
+        memory.store(4_000_000_000, 7).unwrap();
+        assert_eq!(memory.load(4_000_000_000), Ok(7));
+    }
+
+    #[test]
+    fn linear_store_load() {
+        let mut memory = Memory::linear(4);
+        assert_eq!(memory.load(3), Ok(0));
+        memory.store(3, 7).unwrap();
+        assert_eq!(memory.load(3), Ok(7));
+        assert_eq!(memory.store(4, 7), Err(RuntimeError::OutOfBounds(4)));
+        assert_eq!(memory.load(4), Err(RuntimeError::OutOfBounds(4)));
+    }
+
+    #[test]
+    fn entries() {
+        let mut memory = Memory::default();
+        memory.store(9, 1).unwrap();
+        memory.store(2, 3).unwrap();
+        assert_eq!(memory.entries(), vec![(2, 3), (9, 1)]);
+
+        let mut memory = Memory::linear(4);
+        memory.store(3, 7).unwrap();
+        assert_eq!(memory.entries(), vec![(3, 7)]);
+    }
+
+    #[test]
+    fn wrapping_store_load() {
+        let mut memory = Memory::wrapping(4);
+        memory.store(u32::MAX, 7).unwrap();
+        assert_eq!(memory.load(3), Ok(7));
+        assert_eq!(
+            Memory::wrapping(0).load(0),
+            Err(RuntimeError::OutOfBounds(0))
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::interpreter::{Interpreter, OverflowMode};
+use crate::syntax::{Expr, ExprKind, Stmt, StmtKind};
+use crate::value::Value;
+use crate::visitor::Visitor;
+
+/// Replace every operation on integer literals by its result, recursively,
+/// so e.g. `x := 2 + 3 * 4` becomes `x := 14`.
+///
+/// Operations which fail, such as `1 / 0`, are left for the interpreter to report.
+/// So are operations which overflow, as their result depends on the `OverflowMode`.
+/// Folding assumes 32-bit words.
+pub fn fold_constants(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    let mut folder = Folder {
+        interpreter: Interpreter::with_overflow_mode(vec![], OverflowMode::Checked),
+    };
+    folder.stmts(stmts)
+}
+
+/// Evaluates constant operations with an interpreter of its own.
+struct Folder {
+    interpreter: Interpreter,
+}
+
+impl Folder {
+    fn stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        let kind = match stmt.kind {
+            StmtKind::Assignment(var, e) => StmtKind::Assignment(var, self.boxed(e)),
+            StmtKind::ConstAssignment(var, e) => StmtKind::ConstAssignment(var, self.boxed(e)),
+            StmtKind::Store(lhs, rhs, width) => {
+                StmtKind::Store(self.boxed(lhs), self.boxed(rhs), width)
+            }
+            StmtKind::Goto(e) => StmtKind::Goto(self.boxed(e)),
+            StmtKind::Assert(keyword, e) => StmtKind::Assert(keyword, self.boxed(e)),
+            StmtKind::IfThenElse(cond, iftrue, iffalse) => {
+                let iftrue = Box::new(self.stmt(*iftrue));
+                let iffalse = Box::new(self.stmt(*iffalse));
+                StmtKind::IfThenElse(self.boxed(cond), iftrue, iffalse)
+            }
+            StmtKind::Print(e) => StmtKind::Print(self.boxed(e)),
+            StmtKind::Expression(e) => StmtKind::Expression(self.boxed(e)),
+            StmtKind::While(cond, body) => StmtKind::While(self.boxed(cond), self.stmts(body)),
+            StmtKind::Block(body) => StmtKind::Block(self.stmts(body)),
+            StmtKind::Call(e) => StmtKind::Call(self.boxed(e)),
+            StmtKind::Switch(e, cases, default) => StmtKind::Switch(
+                self.boxed(e),
+                cases
+                    .into_iter()
+                    .map(|(value, body)| (value, self.stmts(body)))
+                    .collect(),
+                default.map(|body| self.stmts(body)),
+            ),
+            kind @ (StmtKind::Label(_)
+            | StmtKind::Return
+            | StmtKind::Halt
+            | StmtKind::Nop
+            | StmtKind::Dump) => kind,
+        };
+        Stmt::new(kind, stmt.span)
+    }
+
+    /// Fold a boxed expression, reusing its allocation.
+    fn boxed(&mut self, mut e: Box<Expr>) -> Box<Expr> {
+        *e = self.expr(*e);
+        e
+    }
+
+    fn expr(&mut self, e: Expr) -> Expr {
+        let kind = match e.kind {
+            ExprKind::Binary(lhs, op, rhs) => {
+                ExprKind::Binary(self.boxed(lhs), op, self.boxed(rhs))
+            }
+            ExprKind::Unary(op, rhs) => ExprKind::Unary(op, self.boxed(rhs)),
+            ExprKind::Load(e, width) => ExprKind::Load(self.boxed(e), width),
+            ExprKind::Conditional(cond, iftrue, iffalse) => {
+                ExprKind::Conditional(self.boxed(cond), self.boxed(iftrue), self.boxed(iffalse))
+            }
+            ExprKind::Call(name, args) => {
+                ExprKind::Call(name, args.into_iter().map(|arg| self.expr(arg)).collect())
+            }
+            ExprKind::Array(items) => {
+                ExprKind::Array(items.into_iter().map(|item| self.expr(item)).collect())
+            }
+            ExprKind::Index(array, index) => ExprKind::Index(self.boxed(array), self.boxed(index)),
+            ExprKind::Assign(var, value) => ExprKind::Assign(var, self.boxed(value)),
+            kind => kind,
+        };
+        let e = Expr::new(kind, e.span);
+        match &e.kind {
+            ExprKind::Binary(lhs, _, rhs) if is_int(lhs) && is_int(rhs) => self.fold(e),
+            ExprKind::Unary(_, rhs) if is_int(rhs) => self.fold(e),
+            _ => e,
+        }
+    }
+
+    /// Replace a constant operation by its value, unless evaluating it fails.
+    fn fold(&mut self, e: Expr) -> Expr {
+        match self.interpreter.visit_expr(&e) {
+            Ok(Value::Int(val)) => Expr::new(ExprKind::Val(val), e.span),
+            _ => e,
+        }
+    }
+}
+
+/// True if the expression is an integer literal.
+fn is_int(e: &Expr) -> bool {
+    matches!(e.kind, ExprKind::Val(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn fold(src: &str) -> Vec<String> {
+        let stmts = Parser::new(Scanner::new(src)).collect();
+        fold_constants(stmts)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        assert_eq!(fold("x := 2 + 3 * 4"), vec!["x := 14"]);
+        assert_eq!(fold("x := -(1 - 1) + not 0"), vec!["x := 1"]);
+        assert_eq!(fold("x := y + 2 * 3"), vec!["x := y + 6"]);
+    }
+
+    #[test]
+    fn fold_keeps_failures() {
+        assert_eq!(fold("x := 1 / 0"), vec!["x := 1 / 0"]);
+        assert_eq!(fold("x := (1 + 1) / (2 - 2)"), vec!["x := 2 / 0"]);
+        assert_eq!(fold("x := 4294967295 + 1"), vec!["x := 4294967295 + 1"]);
+    }
+
+    #[test]
+    fn fold_nested_statements() {
+        assert_eq!(
+            fold("while i < 2 * 5 do { store(1 + 1, load(3 - 1)) } end"),
+            vec!["while i < 10 do\n  {\n    store(2, load(2))\n  }\nend"]
+        );
+    }
+
+    #[test]
+    fn fold_keeps_span() {
+        let stmts = Parser::new(Scanner::new("x := 2 + 3")).collect::<Vec<_>>();
+        let folded = fold_constants(stmts.clone());
+        let (StmtKind::Assignment(_, before), StmtKind::Assignment(_, after)) =
+            (&stmts[0].kind, &folded[0].kind)
+        else {
+            panic!("expected assignments");
+        };
+        assert_eq!(after.kind, ExprKind::Val(5));
+        assert_eq!(after.span(), before.span());
+    }
+}
@@ -1,64 +1,234 @@
 use crate::tokens::{Token, TokenType};
-use crate::Result;
+use crate::value::WordWidth;
 use crate::{
-    scanner::Scanner,
-    syntax::{Expr, Stmt},
+    scanner::{ScanError, Scanner},
+    syntax::{Expr, ExprKind, Span, Stmt, StmtKind},
 };
 use std::{
-    fmt::Display,
-    iter::{Iterator, Peekable},
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::{self, Display},
+    iter::Iterator,
 };
 use thiserror::Error;
 use tracing::{event, Level};
 
+/// The token at which a parse error occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    /// The line of the offending token.
+    pub line: usize,
+    /// The column at which the offending token starts.
+    pub column: usize,
+    /// The text of the offending token.
+    pub lexeme: String,
+}
+
+impl From<&Token> for Location {
+    fn from(token: &Token) -> Self {
+        Self {
+            line: token.line,
+            column: token.column,
+            lexeme: token.lexeme.clone(),
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[line {}] near '{}'", self.line, self.lexeme)
+    }
+}
+
+/// The lexeme of the location past the last token.
+const END_OF_INPUT: &str = "end of input";
+
 /// An enum used for error reporting.
 #[derive(Error, Debug, Clone, PartialEq)]
 pub enum ParseError {
     /// A statement is somehow invalid.
-    #[error("Parsing statement failed.")]
-    Stmt(&'static str),
+    #[error("{0}: {1}")]
+    Stmt(Location, &'static str),
 
     /// An expression is somehow invalid.
-    #[error("Parsing expression failed.")]
-    Expr(&'static str),
+    #[error("{0}: {1}")]
+    Expr(Location, &'static str),
 
     /// A different token was expected.
     ///
     /// Note: typically several tokens are expected,
     /// we just use the Expr and Stmt error types for those.
     /// There is probably a better solution.
-    #[error("Expected different token type.")]
-    Expected(TokenType),
+    #[error("{0}: Expected '{1}'.")]
+    Expected(Location, TokenType),
+
+    /// The scanner reported an error.
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+}
+
+impl ParseError {
+    /// True if the error is due to the input ending too soon,
+    /// i.e. more input could make it parse.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            ParseError::Stmt(at, _) | ParseError::Expr(at, _) | ParseError::Expected(at, _) => {
+                at.lexeme == END_OF_INPUT
+            }
+            ParseError::Scan(err) => matches!(err, ScanError::UnterminatedString { .. }),
+        }
+    }
+
+    /// The line and column at which the error occurred.
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            ParseError::Stmt(at, _) | ParseError::Expr(at, _) | ParseError::Expected(at, _) => {
+                (at.line, at.column)
+            }
+            ParseError::Scan(err) => err.position(),
+        }
+    }
+}
+
+impl Eq for ParseError {}
+
+/// Errors are ordered by position, and then by message.
+impl Ord for ParseError {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.position()
+            .cmp(&other.position())
+            .then_with(|| self.to_string().cmp(&other.to_string()))
+    }
 }
 
+impl PartialOrd for ParseError {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The result of parsing part of the token stream.
+/// The error is boxed to keep the frames of the recursive descent small.
+type Result<T> = std::result::Result<T, Box<ParseError>>;
+
 #[doc(hidden)]
-fn err_expr<T>(msg: &'static str) -> Result<T> {
-    Err(Box::new(ParseError::Expr(msg)))
+fn err_expr<T>(at: Location, msg: &'static str) -> Result<T> {
+    Err(Box::new(ParseError::Expr(at, msg)))
 }
 
 #[doc(hidden)]
-fn err_stmt<T>(msg: &'static str) -> Result<T> {
-    Err(Box::new(ParseError::Stmt(msg)))
+fn err_stmt<T>(at: Location, msg: &'static str) -> Result<T> {
+    Err(Box::new(ParseError::Stmt(at, msg)))
 }
 
 #[doc(hidden)]
-fn err_expected<T>(expected: TokenType) -> Result<T> {
-    Err(Box::new(ParseError::Expected(expected)))
+fn err_expected<T>(at: Location, expected: TokenType) -> Result<T> {
+    Err(Box::new(ParseError::Expected(at, expected)))
 }
 
 #[doc(hidden)]
-static BINARY_OPS: [TokenType; 4] = [
+static BINARY_OPS: [TokenType; 19] = [
     TokenType::Plus,
     TokenType::Minus,
     TokenType::Star,
+    TokenType::StarStar,
     TokenType::Slash,
+    TokenType::Percent,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::EqualEqual,
+    TokenType::BangEqual,
+    TokenType::And,
+    TokenType::Or,
+    TokenType::Ampersand,
+    TokenType::Pipe,
+    TokenType::Caret,
+    TokenType::LessLess,
+    TokenType::GreaterGreater,
 ];
 
+/// The built-in functions, along with the number of arguments they take.
+pub(crate) static BUILTINS: [(&str, usize); 11] = [
+    ("min", 2),
+    ("max", 2),
+    ("abs", 1),
+    ("isqrt", 1),
+    ("popcount", 1),
+    ("clz", 1),
+    ("ctz", 1),
+    ("rand", 0),
+    ("steps", 0),
+    ("pc", 0),
+    ("get_input_line", 0),
+];
+
+/// How deeply expressions and statements may nest,
+/// so that parsing crafted input cannot overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// The sources `get_input` can read from.
+pub(crate) static INPUT_CHANNELS: [&str; 3] = ["stdin", "argv", "env"];
+
+/// True if the token is `:=` or a compound assignment operator such as `+=`.
+fn is_assignment(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Assign
+            | TokenType::PlusEqual
+            | TokenType::MinusEqual
+            | TokenType::StarEqual
+            | TokenType::SlashEqual
+    )
+}
+
+/// A token stream with any number of tokens of lookahead, buffered in a ring.
+/// Unlike `Peekable`, it keeps the Scanner accessible.
+#[derive(Debug, Clone)]
+struct Lookahead {
+    scanner: Scanner,
+    peeked: VecDeque<Token>,
+    line: usize,
+    /// The span of the last consumed token.
+    last: Span,
+}
+
+impl Iterator for Lookahead {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.peeked.pop_front().or_else(|| self.scanner.next());
+        if let Some(token) = &token {
+            self.line = token.line;
+            self.last = Span::of(token);
+        }
+        token
+    }
+}
+
+impl Lookahead {
+    /// Returns the next token without consuming it.
+    fn peek(&mut self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the token `n` tokens after the next one without consuming anything,
+    /// so `peek_nth(0)` is the next token.
+    fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        while self.peeked.len() <= n {
+            self.peeked.push_back(self.scanner.next()?);
+        }
+        self.peeked.get(n)
+    }
+}
+
 /// Parser consumes a Scanner, turning the Tokens into a Syntax Tree.
 /// The Parser can in turn be consumed by an Interpreter.
 #[derive(Debug, Clone)]
 pub struct Parser {
-    scanner: Peekable<Scanner>,
+    scanner: Lookahead,
+    depth: usize,
 }
 
 impl Display for Parser {
@@ -74,13 +244,13 @@ impl Iterator for Parser {
     type Item = Stmt;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Ok(stmt) = self.statement() {
-            Some(stmt)
-        } else if self.is_at_end() {
-            None
-        } else {
-            self.synchronize();
-            self.next()
+        loop {
+            self.skip_semicolons();
+            match self.statement() {
+                Ok(stmt) => return Some(stmt),
+                Err(_) if self.is_at_end() => return None,
+                Err(_) => self.synchronize(),
+            }
         }
     }
 }
@@ -89,22 +259,93 @@ impl Parser {
     /// Create a new parser from a Scanner, i.e. a stream of Tokens.
     pub fn new(scanner: Scanner) -> Self {
         Self {
-            scanner: scanner.peekable(),
+            scanner: Lookahead {
+                scanner,
+                peeked: VecDeque::new(),
+                line: 1,
+                last: Span::default(),
+            },
+            depth: 0,
         }
     }
 
-    /// Once parsing has failed, try to advance to the next statement.
+    /// The error which ended the token stream early, if any.
+    pub fn scan_error(&self) -> Option<&ScanError> {
+        self.scanner.scanner.failure()
+    }
+
+    /// Parse the whole token stream, collecting every statement and every error,
+    /// including those reported by the scanner. The errors are sorted by position.
+    pub fn parse_all(mut self) -> (Vec<Stmt>, Vec<ParseError>) {
+        let mut stmts = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            self.skip_semicolons();
+            if self.is_at_end() {
+                break;
+            }
+            match self.statement() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => {
+                    errors.push(*err);
+                    if !self.is_at_end() {
+                        self.synchronize();
+                    }
+                }
+            }
+        }
+        let scanner = &self.scanner.scanner;
+        let scan_errors = scanner.errors().iter().chain(scanner.failure());
+        errors.extend(scan_errors.cloned().map(ParseError::Scan));
+        errors.sort();
+        (stmts, errors)
+    }
+
+    /// Parse the whole token stream as one program.
+    /// Unlike the iterator, which skips what it cannot parse,
+    /// any tokens left over after the last complete statement are an error.
+    /// Returns the first scan error if there is one, as it often causes parse errors,
+    /// and otherwise the first error by position.
+    pub fn into_program(self) -> std::result::Result<Vec<Stmt>, ParseError> {
+        let (stmts, errors) = self.parse_all();
+        let scan_error = errors
+            .iter()
+            .position(|err| matches!(err, ParseError::Scan(_)));
+        match errors.into_iter().nth(scan_error.unwrap_or(0)) {
+            Some(err) => Err(err),
+            None => Ok(stmts),
+        }
+    }
+
+    /// Once parsing has failed, try to advance to the next statement,
+    /// i.e. a keyword which begins one, or a variable followed by an assignment operator.
+    /// A failed statement has always consumed its first token, so recovery makes progress.
     fn synchronize(&mut self) {
         event!(Level::INFO, "call synchronize");
-        self.scanner.next();
         while !self.is_at_end() {
+            if self.at_assignment() {
+                return;
+            }
             if let Some(token) = self.scanner.peek() {
                 match token.token_type {
-                    TokenType::Assign
-                    | TokenType::Store
+                    TokenType::Store
+                    | TokenType::Store8
+                    | TokenType::Store16
                     | TokenType::Goto
                     | TokenType::Assert
-                    | TokenType::If => return,
+                    | TokenType::If
+                    | TokenType::Label
+                    | TokenType::Print
+                    | TokenType::While
+                    | TokenType::LeftBrace
+                    | TokenType::Call
+                    | TokenType::Return
+                    | TokenType::Halt
+                    | TokenType::Const
+                    | TokenType::Switch
+                    | TokenType::Nop
+                    | TokenType::Dump
+                    | TokenType::Semicolon => return,
                     _ => {
                         self.scanner.next();
                     }
@@ -115,53 +356,174 @@ impl Parser {
 
     /// Attempts to parse a statement.
     fn statement(&mut self) -> Result<Stmt> {
+        self.nested(Self::statement_inner)
+    }
+
+    fn statement_inner(&mut self) -> Result<Stmt> {
+        if self.at_expression() {
+            let expr = self.expression()?;
+            let span = expr.span;
+            return Ok(Stmt::new(StmtKind::Expression(Box::new(expr)), span));
+        }
         let lhs = match self.scanner.next() {
             Some(token) => token,
-            None => return err_stmt("Expected token, found EOF."),
+            None => return err_stmt(self.location(), "Expected token, found EOF."),
         };
+        let start = Span::of(&lhs);
 
-        match lhs.token_type {
+        let kind = match lhs.token_type {
             TokenType::Identifier(_) => self.assign(lhs),
-            TokenType::Store => self.store(),
+            TokenType::Store => self.store(WordWidth::U32),
+            TokenType::Store8 => self.store(WordWidth::U8),
+            TokenType::Store16 => self.store(WordWidth::U16),
             TokenType::Goto => self.goto(),
-            TokenType::Assert => self.assert(),
+            TokenType::Assert => self.assert(lhs),
             TokenType::If => self.r#if(),
-            _ => return err_stmt("Expected statement."),
-        }
+            TokenType::Label => self.label(),
+            TokenType::Print => self.print(),
+            TokenType::While => self.r#while(),
+            TokenType::LeftBrace => Ok(StmtKind::Block(self.body(TokenType::RightBrace)?)),
+            TokenType::Call => Ok(StmtKind::Call(Box::new(self.expression()?))),
+            TokenType::Return => Ok(StmtKind::Return),
+            TokenType::Halt => Ok(StmtKind::Halt),
+            TokenType::Nop => Ok(StmtKind::Nop),
+            TokenType::Dump => Ok(StmtKind::Dump),
+            TokenType::Const => self.r#const(),
+            TokenType::Switch => self.switch(),
+            _ if self.check_assignment() => {
+                err_stmt((&lhs).into(), "Can only assign to a variable.")
+            }
+            _ => err_stmt((&lhs).into(), "Expected statement."),
+        }?;
+        Ok(Stmt::new(kind, self.span_from(start)))
+    }
+
+    /// The span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.scanner.last)
     }
 
     /// Attempt to parse an expression.
+    /// A conditional `c ? a : b` binds looser than any operator, and groups to the right.
     fn expression(&mut self) -> Result<Expr> {
+        self.nested(Self::expression_inner)
+    }
+
+    fn expression_inner(&mut self) -> Result<Expr> {
+        let condition = self.ops(0)?;
+        if !self.check(TokenType::Question) {
+            return Ok(condition);
+        }
+        self.scanner.next();
+        let first = self.expression()?;
+        self.expect(TokenType::Colon)?;
+        let second = self.expression()?;
+        let span = condition.span.to(second.span);
+        Ok(Expr::new(
+            ExprKind::Conditional(Box::new(condition), Box::new(first), Box::new(second)),
+            span,
+        ))
+    }
+
+    /// Attempt to parse an operand, i.e. anything that can appear on either side of an operator.
+    fn primary(&mut self) -> Result<Expr> {
         let lhs = match self.scanner.peek() {
-            Some(token) => token,
-            None => return err_expr("Expected token, found EOF."),
+            Some(token) => token.clone(),
+            None => return err_expr(self.location(), "Expected token, found EOF."),
         };
+        let start = Span::of(&lhs);
 
-        match lhs.token_type {
-            TokenType::Load => self.load(),
-            TokenType::GetInput => Ok(Expr::GetInput("stdin".into())),
-            TokenType::Identifier(_) | TokenType::Value(_) => self.ops(0),
-            TokenType::Plus | TokenType::Minus => self.unary(),
-            _ => return err_expr("Expected Load, GetInput, Identifier or Value."),
-        }
+        let kind = match lhs.token_type {
+            TokenType::Load => self.load(WordWidth::U32),
+            TokenType::Load8 => self.load(WordWidth::U8),
+            TokenType::Load16 => self.load(WordWidth::U16),
+            TokenType::GetInput => self.get_input(),
+            TokenType::Identifier(_) if self.at_assignment() => {
+                err_expr((&lhs).into(), "Expected expression, found assignment.")
+            }
+            TokenType::Identifier(_) => {
+                self.scanner.next();
+                if self.check(TokenType::LeftParen) {
+                    self.call(lhs)
+                } else {
+                    Ok(ExprKind::Var(lhs))
+                }
+            }
+            TokenType::Value(val) => {
+                self.scanner.next();
+                Ok(ExprKind::Val(val))
+            }
+            TokenType::Float(val) => {
+                self.scanner.next();
+                Ok(ExprKind::Float(val))
+            }
+            TokenType::Str(val) => {
+                self.scanner.next();
+                Ok(ExprKind::Str(val))
+            }
+            TokenType::Register(_) => err_expr(
+                (&lhs).into(),
+                "A named register can only be stored or loaded.",
+            ),
+            TokenType::Plus | TokenType::Minus | TokenType::Not => self.unary(),
+            TokenType::LeftParen => return self.grouping(),
+            TokenType::LeftBracket => self.array(),
+            _ => err_expr(
+                (&lhs).into(),
+                "Expected Load, GetInput, Identifier, Value or '('.",
+            ),
+        }?;
+        Ok(Expr::new(kind, self.span_from(start)))
     }
 
-    fn binary_binding_power(token_type: &TokenType) -> Result<(u8, u8)> {
-        let res = match token_type {
-            TokenType::Plus | TokenType::Minus => (1, 2),
-            TokenType::Star | TokenType::Slash => (3, 4),
-            _ => return err_expr("Expected operator."),
+    pub(crate) fn prefix_binding_power(op: &Token) -> Result<u8> {
+        let res = match op.token_type {
+            TokenType::Plus | TokenType::Minus | TokenType::Not => 19,
+            _ => return err_expr(op.into(), "Expected unary operator."),
+        };
+        Ok(res)
+    }
+
+    pub(crate) fn binary_binding_power(op: &Token) -> Result<(u8, u8)> {
+        let res = match op.token_type {
+            TokenType::Or => (1, 2),
+            TokenType::And => (3, 4),
+            TokenType::Pipe => (5, 6),
+            TokenType::Caret => (7, 8),
+            TokenType::Ampersand => (9, 10),
+            TokenType::Less
+            | TokenType::LessEqual
+            | TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::EqualEqual
+            | TokenType::BangEqual => (11, 12),
+            TokenType::Plus | TokenType::Minus => (13, 14),
+            TokenType::LessLess | TokenType::GreaterGreater => (15, 16),
+            TokenType::Star | TokenType::Slash | TokenType::Percent => (17, 18),
+            TokenType::StarStar => (20, 19),
+            _ => return err_expr(op.into(), "Expected operator."),
         };
         Ok(res)
     }
 
     /// Attempt to parse a unary expression.
-    /// TODO: Not really sure what we want here, to be honest.
-    fn unary(&mut self) -> Result<Expr> {
-        Ok(Expr::Unary(
-            self.scanner.next().unwrap(),
-            Box::new(self.expression()?),
-        ))
+    /// Unary operators bind tighter than any binary operator but `**`,
+    /// so `-1 + 2` is `(-1) + 2` while `-2 ** 2` is `-(2 ** 2)`.
+    fn unary(&mut self) -> Result<ExprKind> {
+        let op = self.advance()?;
+        let right_binding_power = Self::prefix_binding_power(&op)?;
+        let rhs = self.ops(right_binding_power)?;
+        Ok(ExprKind::Unary(op, Box::new(rhs)))
+    }
+
+    /// Attempt to parse an expression wrapped in parentheses.
+    /// The span of the expression includes the parentheses.
+    fn grouping(&mut self) -> Result<Expr> {
+        self.expect(TokenType::LeftParen)?;
+        let start = self.scanner.last;
+        let inner = self.expression()?;
+        self.expect(TokenType::RightParen)?;
+        Ok(Expr::new(inner.kind, self.span_from(start)))
     }
 
     /// Attempt to parse a series of operations.
@@ -169,34 +531,31 @@ impl Parser {
     /// [SPPP](https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html)
     /// to get the correct precedence and associativity.
     fn ops(&mut self, min_binding_power: u8) -> Result<Expr> {
-        let mut lhs = {
-            let parse_err: Result<Expr> = err_expr("Expected value or identifier.");
-            match self.scanner.next() {
-                Some(ref token) => match &token.token_type {
-                    TokenType::Value(val) => Expr::Val(*val),
-                    TokenType::Identifier(var) => Expr::Var(var.clone()),
-                    _ => return parse_err,
-                },
-                None => return parse_err,
-            }
-        };
-        loop {
-            let op = match self.scanner.peek() {
-                Some(op) => op.clone(),
-                None => break,
-            };
+        self.nested(|parser| parser.ops_inner(min_binding_power))
+    }
 
+    fn ops_inner(&mut self, min_binding_power: u8) -> Result<Expr> {
+        let mut lhs = self.primary()?;
+        while let Some(op) = self.scanner.peek() {
+            let op = op.clone();
             if BINARY_OPS.contains(&op.token_type) {
-                let (left_binding_power, right_binding_power) =
-                    Self::binary_binding_power(&op.token_type)?;
+                let (left_binding_power, right_binding_power) = Self::binary_binding_power(&op)?;
                 if left_binding_power < min_binding_power {
                     break;
                 }
 
-                self.scanner.next().unwrap();
+                self.advance()?;
                 let rhs = self.ops(right_binding_power)?;
 
-                lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+                let span = lhs.span.to(rhs.span);
+                lhs = Expr::new(ExprKind::Binary(Box::new(lhs), op, Box::new(rhs)), span);
+            } else if op.token_type == TokenType::LeftBracket {
+                // An index binds tighter than any operator, so it never ends the loop.
+                self.scanner.next();
+                let index = self.expression()?;
+                self.expect(TokenType::RightBracket)?;
+                let span = self.span_from(lhs.span);
+                lhs = Expr::new(ExprKind::Index(Box::new(lhs), Box::new(index)), span);
             } else {
                 break;
             }
@@ -205,62 +564,358 @@ impl Parser {
         Ok(lhs)
     }
 
-    /// Attempt to parse the load expression.
-    fn load(&mut self) -> Result<Expr> {
-        self.scanner.next().unwrap();
+    /// Attempt to parse the arguments of a call to the built-in function `name`.
+    fn call(&mut self, name: Token) -> Result<ExprKind> {
+        let arity = match BUILTINS.iter().find(|(builtin, _)| *builtin == name.lexeme) {
+            Some((_, arity)) => *arity,
+            None => return err_expr((&name).into(), "Unknown function."),
+        };
         self.expect(TokenType::LeftParen)?;
-        let inner = self.expression()?;
+        let mut args = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            args.push(self.expression()?);
+            while self.check(TokenType::Comma) {
+                self.scanner.next();
+                args.push(self.expression()?);
+            }
+        }
         self.expect(TokenType::RightParen)?;
-        Ok(Expr::Load(Box::new(inner)))
+        if args.len() != arity {
+            return err_expr((&name).into(), "Wrong number of arguments.");
+        }
+        Ok(ExprKind::Call(name, args))
     }
 
-    /// Attempt to parse the assignment statement.
-    fn assign(&mut self, identifier: Token) -> Result<Stmt> {
-        let assign = self.scanner.next().unwrap();
-        if assign.token_type == TokenType::Assign {
-            let expr = self.expression()?;
-            Ok(Stmt::Assignment(identifier, Box::new(expr)))
+    /// Attempt to parse an array literal.
+    fn array(&mut self) -> Result<ExprKind> {
+        self.expect(TokenType::LeftBracket)?;
+        let mut items = Vec::new();
+        if !self.check(TokenType::RightBracket) {
+            items.push(self.expression()?);
+            while self.check(TokenType::Comma) {
+                self.scanner.next();
+                items.push(self.expression()?);
+            }
+        }
+        self.expect(TokenType::RightBracket)?;
+        Ok(ExprKind::Array(items))
+    }
+
+    /// Attempt to parse the get_input expression.
+    /// The `env` channel takes the name of the environment variable to read.
+    fn get_input(&mut self) -> Result<ExprKind> {
+        self.expect(TokenType::GetInput)?;
+        self.expect(TokenType::LeftParen)?;
+        let location = self.location();
+        let channel = self.identifier(location.clone(), "Expected input channel.")?;
+        if !INPUT_CHANNELS.contains(&channel.as_str()) {
+            return err_expr(location, "Unknown input channel.");
+        }
+        let key = if channel == "env" {
+            self.expect(TokenType::Comma)?;
+            let location = self.location();
+            Some(self.identifier(location, "Expected environment variable name.")?)
         } else {
-            err_stmt("Invalid assignment.".into())
+            None
+        };
+        self.expect(TokenType::RightParen)?;
+        Ok(ExprKind::GetInput(channel, key))
+    }
+
+    /// Consume an identifier and return its name, or fail with `msg` at `location`.
+    fn identifier(&mut self, location: Location, msg: &'static str) -> Result<String> {
+        match self.scanner.next() {
+            Some(Token {
+                token_type: TokenType::Identifier(name),
+                ..
+            }) => Ok(name),
+            _ => err_expr(location, msg),
+        }
+    }
+
+    /// Attempt to parse the load expression, of a register of `width` bits.
+    fn load(&mut self, width: WordWidth) -> Result<ExprKind> {
+        self.advance()?;
+        self.expect(TokenType::LeftParen)?;
+        let inner = self.address()?;
+        self.expect(TokenType::RightParen)?;
+        Ok(ExprKind::Load(Box::new(inner), width))
+    }
+
+    /// Attempt to parse the address of a `store` or `load`,
+    /// which is either a named register such as `@r0` or an expression.
+    fn address(&mut self) -> Result<Expr> {
+        match self.scanner.peek() {
+            Some(
+                token @ Token {
+                    token_type: TokenType::Register(name),
+                    ..
+                },
+            ) => {
+                let register = Expr::new(ExprKind::Register(name.clone()), Span::of(token));
+                self.scanner.next();
+                Ok(register)
+            }
+            _ => self.expression(),
+        }
+    }
+
+    /// Attempt to parse the assignment statement.
+    /// A compound assignment such as `x += e` is desugared into `x := x + e`.
+    fn assign(&mut self, identifier: Token) -> Result<StmtKind> {
+        let assign = match self.scanner.next() {
+            Some(token) => token,
+            None => return err_stmt(self.location(), "Invalid assignment."),
+        };
+        let op = match assign.token_type {
+            TokenType::Assign => None,
+            TokenType::PlusEqual => Some(TokenType::Plus),
+            TokenType::MinusEqual => Some(TokenType::Minus),
+            TokenType::StarEqual => Some(TokenType::Star),
+            TokenType::SlashEqual => Some(TokenType::Slash),
+            _ => return err_stmt((&assign).into(), "Invalid assignment."),
+        };
+        let mut expr = match op {
+            None => self.assigned_value()?,
+            Some(_) => self.expression()?,
+        };
+        if let Some(op) = op {
+            let var = Expr::new(ExprKind::Var(identifier.clone()), Span::of(&identifier));
+            let span = var.span.to(expr.span);
+            let op = Token {
+                lexeme: op.to_string(),
+                token_type: op,
+                ..assign
+            };
+            expr = Expr::new(ExprKind::Binary(Box::new(var), op, Box::new(expr)), span);
+        }
+        Ok(StmtKind::Assignment(identifier, Box::new(expr)))
+    }
+
+    /// Attempt to parse the value of an `:=` assignment,
+    /// which may assign another variable in turn, as in `a := b := 5`.
+    fn assigned_value(&mut self) -> Result<Expr> {
+        let chained = matches!(
+            self.scanner.peek(),
+            Some(Token {
+                token_type: TokenType::Identifier(_),
+                ..
+            })
+        ) && matches!(
+            self.scanner.peek_nth(1),
+            Some(Token {
+                token_type: TokenType::Assign,
+                ..
+            })
+        );
+        if !chained {
+            return self.expression();
         }
+        let identifier = self.advance()?;
+        self.advance()?;
+        let value = self.nested(Self::assigned_value)?;
+        let span = Span::of(&identifier).to(value.span);
+        Ok(Expr::new(
+            ExprKind::Assign(identifier, Box::new(value)),
+            span,
+        ))
+    }
+
+    /// True if the next token is an assignment operator.
+    fn check_assignment(&mut self) -> bool {
+        self.scanner.peek().is_some_and(is_assignment)
+    }
+
+    /// True if the next two tokens begin an assignment statement,
+    /// i.e. a variable followed by an assignment operator.
+    fn at_assignment(&mut self) -> bool {
+        let variable = matches!(
+            self.scanner.peek(),
+            Some(Token {
+                token_type: TokenType::Identifier(_),
+                ..
+            })
+        );
+        variable && self.scanner.peek_nth(1).is_some_and(is_assignment)
+    }
+
+    /// True if the next token begins an expression statement rather than any other,
+    /// i.e. an operand which is not the target of an assignment.
+    fn at_expression(&mut self) -> bool {
+        let operand = self.scanner.peek().is_some_and(|token| {
+            matches!(
+                token.token_type,
+                TokenType::Identifier(_)
+                    | TokenType::Load
+                    | TokenType::Load8
+                    | TokenType::Load16
+                    | TokenType::GetInput
+                    | TokenType::Value(_)
+                    | TokenType::Float(_)
+                    | TokenType::Str(_)
+                    | TokenType::Register(_)
+                    | TokenType::Plus
+                    | TokenType::Minus
+                    | TokenType::Not
+                    | TokenType::LeftParen
+                    | TokenType::LeftBracket
+            )
+        });
+        operand && !self.scanner.peek_nth(1).is_some_and(is_assignment)
     }
 
-    /// Attempt to parse the store statement.
-    fn store(&mut self) -> Result<Stmt> {
+    /// Attempt to parse the const statement.
+    fn r#const(&mut self) -> Result<StmtKind> {
+        let location = self.location();
+        let identifier = match self.scanner.next() {
+            Some(
+                token @ Token {
+                    token_type: TokenType::Identifier(_),
+                    ..
+                },
+            ) => token,
+            _ => return err_stmt(location, "Expected constant name."),
+        };
+        self.expect(TokenType::Assign)?;
+        let expr = self.assigned_value()?;
+        Ok(StmtKind::ConstAssignment(identifier, Box::new(expr)))
+    }
+
+    /// Attempt to parse the store statement, to a register of `width` bits.
+    fn store(&mut self, width: WordWidth) -> Result<StmtKind> {
         self.expect(TokenType::LeftParen)?;
-        let left = self.expression()?;
+        let left = self.address()?;
         self.expect(TokenType::Comma)?;
         let right = self.expression()?;
         self.expect(TokenType::RightParen)?;
-        Ok(Stmt::Store(Box::new(left), Box::new(right)))
+        Ok(StmtKind::Store(Box::new(left), Box::new(right), width))
     }
 
     /// Attempt to parse the goto statement.
-    fn goto(&mut self) -> Result<Stmt> {
-        Ok(Stmt::Goto(Box::new(self.expression()?)))
+    fn goto(&mut self) -> Result<StmtKind> {
+        Ok(StmtKind::Goto(Box::new(self.expression()?)))
     }
 
     /// Attempt to parse the assert statement.
-    fn assert(&mut self) -> Result<Stmt> {
-        Ok(Stmt::Assert(Box::new(self.expression()?)))
+    fn assert(&mut self, keyword: Token) -> Result<StmtKind> {
+        Ok(StmtKind::Assert(keyword, Box::new(self.expression()?)))
     }
 
     /// Attempt to parse the IfThenElse statement.
-    fn r#if(&mut self) -> Result<Stmt> {
+    fn r#if(&mut self) -> Result<StmtKind> {
         let condition = self.expression()?;
         self.expect(TokenType::Then)?;
-        self.expect(TokenType::Goto)?;
-        let first = self.expression()?;
+        let first = self.statement()?;
         self.expect(TokenType::Else)?;
-        self.expect(TokenType::Goto)?;
-        let second = self.expression()?;
-        Ok(Stmt::IfThenElse(
+        let second = self.statement()?;
+        Ok(StmtKind::IfThenElse(
             Box::new(condition),
             Box::new(first),
             Box::new(second),
         ))
     }
 
+    /// Attempt to parse the print statement.
+    fn print(&mut self) -> Result<StmtKind> {
+        Ok(StmtKind::Print(Box::new(self.expression()?)))
+    }
+
+    /// Attempt to parse the while statement.
+    fn r#while(&mut self) -> Result<StmtKind> {
+        let condition = self.expression()?;
+        self.expect(TokenType::Do)?;
+        let body = self.body(TokenType::End)?;
+        Ok(StmtKind::While(Box::new(condition), body))
+    }
+
+    /// Attempt to parse the switch statement.
+    fn switch(&mut self) -> Result<StmtKind> {
+        let expr = self.expression()?;
+        self.expect(TokenType::LeftBrace)?;
+        let mut cases: Vec<(u32, Vec<Stmt>)> = Vec::new();
+        let mut default = None;
+        self.skip_semicolons();
+        while !self.check(TokenType::RightBrace) {
+            let location = self.location();
+            match self.scanner.next().map(|token| token.token_type) {
+                Some(TokenType::Case) => {
+                    let location = self.location();
+                    let value = match self.scanner.next().map(|token| token.token_type) {
+                        Some(TokenType::Value(value)) => value,
+                        _ => return err_stmt(location, "Expected case value."),
+                    };
+                    if cases.iter().any(|(case, _)| *case == value) {
+                        return err_stmt(location, "Duplicate case.");
+                    }
+                    self.expect(TokenType::Colon)?;
+                    cases.push((value, self.case_body()?));
+                }
+                Some(TokenType::Default) if default.is_none() => {
+                    self.expect(TokenType::Colon)?;
+                    default = Some(self.case_body()?);
+                }
+                Some(TokenType::Default) => return err_stmt(location, "Duplicate default."),
+                None => return err_expected(location, TokenType::RightBrace),
+                _ => return err_stmt(location, "Expected case or default."),
+            }
+        }
+        self.scanner.next();
+        Ok(StmtKind::Switch(Box::new(expr), cases, default))
+    }
+
+    /// Attempt to parse the statements of a case, up to the next `case`, `default` or `}`.
+    fn case_body(&mut self) -> Result<Vec<Stmt>> {
+        let mut body = Vec::new();
+        self.skip_semicolons();
+        while !(self.check(TokenType::Case)
+            || self.check(TokenType::Default)
+            || self.check(TokenType::RightBrace))
+        {
+            if self.is_at_end() {
+                return err_expected(self.location(), TokenType::RightBrace);
+            }
+            body.push(self.statement()?);
+            self.skip_semicolons();
+        }
+        Ok(body)
+    }
+
+    /// Attempt to parse statements up to and including the closing `end` token.
+    fn body(&mut self, end: TokenType) -> Result<Vec<Stmt>> {
+        let mut body = Vec::new();
+        self.skip_semicolons();
+        while !self.check(end.clone()) {
+            if self.is_at_end() {
+                return err_expected(self.location(), end);
+            }
+            body.push(self.statement()?);
+            self.skip_semicolons();
+        }
+        self.scanner.next();
+        Ok(body)
+    }
+
+    /// Attempt to parse the label statement.
+    fn label(&mut self) -> Result<StmtKind> {
+        let location = self.location();
+        let name = match self.scanner.next() {
+            Some(Token {
+                token_type: TokenType::Identifier(name),
+                ..
+            }) => name,
+            _ => return err_stmt(location, "Expected label name."),
+        };
+        self.expect(TokenType::Colon)?;
+        Ok(StmtKind::Label(name))
+    }
+
+    /// Skip any number of statement separators.
+    fn skip_semicolons(&mut self) {
+        while self.check(TokenType::Semicolon) {
+            self.scanner.next();
+        }
+    }
+
     /// True if the next token matches token_type.
     fn check(&mut self, token_type: TokenType) -> bool {
         event!(Level::INFO, "call check");
@@ -278,20 +933,48 @@ impl Parser {
     fn expect(&mut self, token_type: TokenType) -> Result<()> {
         event!(Level::INFO, "call expect");
         if !self.check(token_type.clone()) {
-            err_expected(token_type)
+            err_expected(self.location(), token_type)
         } else {
             self.scanner.next();
             Ok(())
         }
     }
 
+    /// The location of the next token, for error reporting.
+    fn location(&mut self) -> Location {
+        match self.scanner.peek() {
+            Some(token) => token.into(),
+            None => Location {
+                line: self.scanner.line,
+                column: self.scanner.last.end_col + 1,
+                lexeme: END_OF_INPUT.into(),
+            },
+        }
+    }
+
+    /// Consume the next token, which the caller has usually peeked at already.
+    fn advance(&mut self) -> Result<Token> {
+        match self.scanner.next() {
+            Some(token) => Ok(token),
+            None => err_expr(self.location(), "Expected token, found EOF."),
+        }
+    }
+
+    /// Run `parse` one level of nesting deeper, failing if that is too deep.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return err_expr(self.location(), "Nested too deeply.");
+        }
+        self.depth += 1;
+        let res = parse(self);
+        self.depth -= 1;
+        res
+    }
+
     /// True if the stream has run dry.
     fn is_at_end(&mut self) -> bool {
         event!(Level::INFO, "call is_at_end");
-        match self.scanner.peek() {
-            Some(_) => false,
-            None => true,
-        }
+        self.scanner.peek().is_none()
     }
 }
 
@@ -301,11 +984,140 @@ mod tests {
     use crate::scanner::Scanner;
 
     fn statement(src: &str) -> String {
-        format!("{}", Parser::new(Scanner::new(src)).statement().unwrap())
+        sexpr_stmt(&Parser::new(Scanner::new(src)).statement().unwrap())
     }
 
     fn expression(src: &str) -> String {
-        format!("{}", Parser::new(Scanner::new(src)).expression().unwrap())
+        sexpr(&Parser::new(Scanner::new(src)).expression().unwrap())
+    }
+
+    /// Render the structure of a statement, grouping every operation explicitly,
+    /// e.g. `If (x, <, 1) Then Goto 2 Else Print x`.
+    fn sexpr_stmt(s: &Stmt) -> String {
+        let body = |body: &[Stmt]| {
+            let body: Vec<_> = body.iter().map(sexpr_stmt).collect();
+            body.join(", ")
+        };
+        match &s.kind {
+            StmtKind::Assignment(var, expr) => format!("{} := {}", var, sexpr(expr)),
+            StmtKind::Store(lhs, rhs, width) => {
+                format!("Store{}({}, {})", width.suffix(), sexpr(lhs), sexpr(rhs))
+            }
+            StmtKind::Goto(target) => format!("Goto {}", sexpr(target)),
+            StmtKind::Assert(_, expr) => format!("Assert {}", sexpr(expr)),
+            StmtKind::IfThenElse(cond, iftrue, iffalse) => format!(
+                "If {} Then {} Else {}",
+                sexpr(cond),
+                sexpr_stmt(iftrue),
+                sexpr_stmt(iffalse)
+            ),
+            StmtKind::Label(name) => format!("Label {}", name),
+            StmtKind::Print(expr) => format!("Print {}", sexpr(expr)),
+            StmtKind::While(cond, stmts) => {
+                format!("While {} Do [{}] End", sexpr(cond), body(stmts))
+            }
+            StmtKind::Block(stmts) => format!("Block [{}]", body(stmts)),
+            StmtKind::Call(target) => format!("Call {}", sexpr(target)),
+            StmtKind::Return => "Return".into(),
+            StmtKind::Halt => "Halt".into(),
+            StmtKind::Nop => "Nop".into(),
+            StmtKind::Dump => "Dump".into(),
+            StmtKind::Expression(expr) => sexpr(expr),
+            StmtKind::ConstAssignment(var, expr) => format!("Const {} := {}", var, sexpr(expr)),
+            StmtKind::Switch(expr, cases, default) => {
+                let mut branches: Vec<_> = cases
+                    .iter()
+                    .map(|(value, stmts)| format!("Case {} [{}]", value, body(stmts)))
+                    .collect();
+                if let Some(stmts) = default {
+                    branches.push(format!("Default [{}]", body(stmts)));
+                }
+                format!("Switch {} [{}]", sexpr(expr), branches.join(", "))
+            }
+        }
+    }
+
+    /// Render the structure of an expression, grouping every operation explicitly,
+    /// e.g. `(1, +, (2, *, 3))`.
+    fn sexpr(e: &Expr) -> String {
+        let list = |items: &[Expr]| {
+            let items: Vec<_> = items.iter().map(sexpr).collect();
+            items.join(", ")
+        };
+        match &e.kind {
+            ExprKind::Load(reg, width) => format!("Load{}({})", width.suffix(), sexpr(reg)),
+            ExprKind::Binary(lhs, op, rhs) => format!("({}, {}, {})", sexpr(lhs), op, sexpr(rhs)),
+            ExprKind::Unary(op, rhs) => format!("Unary({}, {})", op, sexpr(rhs)),
+            ExprKind::Var(var) => var.to_string(),
+            ExprKind::GetInput(input, None) => format!("GetInput({})", input),
+            ExprKind::GetInput(input, Some(key)) => format!("GetInput({}, {})", input, key),
+            ExprKind::Val(val) => val.to_string(),
+            ExprKind::Float(val) => crate::tokens::float_literal(*val),
+            ExprKind::Str(val) => crate::tokens::quote(val),
+            ExprKind::Conditional(cond, iftrue, iffalse) => {
+                format!("({} ? {} : {})", sexpr(cond), sexpr(iftrue), sexpr(iffalse))
+            }
+            ExprKind::Call(name, args) => format!("{}({})", name, list(args)),
+            ExprKind::Array(items) => format!("[{}]", list(items)),
+            ExprKind::Index(array, index) => format!("{}[{}]", sexpr(array), sexpr(index)),
+            ExprKind::Register(name) => format!("@{}", name),
+            ExprKind::Assign(var, value) => format!("{} := {}", var, sexpr(value)),
+        }
+    }
+
+    /// A span within the first line.
+    fn span(start_col: usize, end_col: usize) -> Span {
+        Span {
+            start_line: 1,
+            start_col,
+            end_line: 1,
+            end_col,
+        }
+    }
+
+    #[test]
+    fn parse_spans() {
+        let stmt = Parser::new(Scanner::new("x := 1 + 2")).next().unwrap();
+        assert_eq!(stmt.span(), span(1, 10));
+        let StmtKind::Assignment(_, expr) = &stmt.kind else {
+            panic!("expected an assignment, found {}", stmt);
+        };
+        assert_eq!(expr.span(), span(6, 10));
+        let ExprKind::Binary(lhs, _, rhs) = &expr.kind else {
+            panic!("expected a binary expression, found {}", expr);
+        };
+        assert_eq!((lhs.span(), rhs.span()), (span(6, 6), span(10, 10)));
+    }
+
+    #[test]
+    fn parse_spans_nested() {
+        let mut parser = Parser::new(Scanner::new("(1 + 22) * -abs(x)"));
+        let expr = parser.expression().unwrap();
+        assert_eq!(expr.span(), span(1, 18));
+        let ExprKind::Binary(lhs, _, rhs) = &expr.kind else {
+            panic!("expected a binary expression, found {}", expr);
+        };
+        assert_eq!((lhs.span(), rhs.span()), (span(1, 8), span(12, 18)));
+
+        let mut parser = Parser::new(Scanner::new("while 1 do\n  print \"a\nb\"\nend"));
+        let stmt = parser.next().unwrap();
+        let end = Span {
+            start_line: 1,
+            start_col: 1,
+            end_line: 4,
+            end_col: 3,
+        };
+        assert_eq!(stmt.span(), end);
+        let StmtKind::While(_, body) = &stmt.kind else {
+            panic!("expected a while loop, found {}", stmt);
+        };
+        let print = Span {
+            start_line: 2,
+            start_col: 3,
+            end_line: 3,
+            end_col: 2,
+        };
+        assert_eq!(body[0].span(), print);
     }
 
     #[test]
@@ -328,6 +1140,11 @@ mod tests {
         expression("1 * 1");
     }
 
+    #[test]
+    fn parse_remainder() {
+        assert_eq!(expression("1 + 7 % 3 * 2"), "(1, +, ((7, %, 3), *, 2))");
+    }
+
     #[test]
     fn parse_unary_minus() {
         expression("-1");
@@ -338,6 +1155,81 @@ mod tests {
         expression("+1");
     }
 
+    #[test]
+    fn parse_precedence_unary() {
+        assert_eq!(expression("-1 + 2"), "(Unary(-, 1), +, 2)");
+        assert_eq!(expression("2 * -x"), "(2, *, Unary(-, x))");
+    }
+
+    #[test]
+    fn parse_precedence_conditional() {
+        assert_eq!(expression("1 ? 2 : 3"), "(1 ? 2 : 3)");
+        assert_eq!(
+            expression("x < 1 or y ? 2 + 3 : -4"),
+            "(((x, <, 1), or, y) ? (2, +, 3) : Unary(-, 4))"
+        );
+        assert_eq!(expression("a ? b : c ? d : e"), "(a ? b : (c ? d : e))");
+        assert_eq!(expression("a ? b ? c : d : e"), "(a ? (b ? c : d) : e)");
+        assert_eq!(expression("(a ? b : c) + 1"), "((a ? b : c), +, 1)");
+    }
+
+    #[test]
+    fn parse_conditional_missing_colon() {
+        let err = Parser::new(Scanner::new("1 ? 2 3"))
+            .expression()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "[line 1] near '3': Expected ':'.");
+    }
+
+    #[test]
+    fn expected_token_shows_source_text() {
+        let err = Parser::new(Scanner::new("while 1 print 2 end"))
+            .statement()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "[line 1] near 'print': Expected 'do'.");
+        let err = Parser::new(Scanner::new("{ print 1"))
+            .statement()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 1] near 'end of input': Expected '}'."
+        );
+    }
+
+    #[test]
+    fn parse_builtin_call() {
+        assert_eq!(
+            expression("max(1, min(x, 2)) + abs(3)"),
+            "(max(1, min(x, 2)), +, abs(3))"
+        );
+        let parse_err = |src| {
+            Parser::new(Scanner::new(src))
+                .expression()
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            parse_err("max(1)"),
+            "[line 1] near 'max': Wrong number of arguments."
+        );
+        assert_eq!(
+            parse_err("abs()"),
+            "[line 1] near 'abs': Wrong number of arguments."
+        );
+        assert_eq!(parse_err("f(1)"), "[line 1] near 'f': Unknown function.");
+        assert_eq!(
+            parse_err("popcount(1, 2)"),
+            "[line 1] near 'popcount': Wrong number of arguments."
+        );
+    }
+
+    #[test]
+    fn parse_precedence_power() {
+        assert_eq!(expression("2 ** 3 ** 2"), "(2, **, (3, **, 2))");
+        assert_eq!(expression("2 * 3 ** 2"), "(2, *, (3, **, 2))");
+        assert_eq!(expression("-2 ** 2"), "Unary(-, (2, **, 2))");
+    }
+
     #[test]
     fn parse_assignment() {
         statement("x := 1");
@@ -348,6 +1240,18 @@ mod tests {
         statement("store(1, 1)");
     }
 
+    #[test]
+    fn parse_named_registers() {
+        statement("store(@r0, load(@r1))");
+        let err = Parser::new(Scanner::new("x := @r0 + 1"))
+            .statement()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 1] near '@r0': A named register can only be stored or loaded."
+        );
+    }
+
     #[test]
     fn parse_goto() {
         statement("goto 1");
@@ -363,6 +1267,357 @@ mod tests {
         statement("if 1 then goto 2 else goto 3");
     }
 
+    #[test]
+    fn parse_if_then_else_statements() {
+        assert_eq!(
+            statement("if 1 then x := 2 else x := 3"),
+            "If 1 Then x := 2 Else x := 3"
+        );
+        assert_eq!(
+            statement("if x then print x else goto 3"),
+            "If x Then Print x Else Goto 3"
+        );
+    }
+
+    #[test]
+    fn parse_chained_assignment() {
+        assert_eq!(statement("a := b := 5"), "a := b := 5");
+        let stmt = Parser::new(Scanner::new("a := b := c := 1 + 2"))
+            .next()
+            .unwrap();
+        let StmtKind::Assignment(a, value) = &stmt.kind else {
+            panic!("expected an assignment, found {}", stmt);
+        };
+        let ExprKind::Assign(b, value) = &value.kind else {
+            panic!("expected an assignment, found {}", value);
+        };
+        let ExprKind::Assign(c, value) = &value.kind else {
+            panic!("expected an assignment, found {}", value);
+        };
+        assert_eq!((&*a.lexeme, &*b.lexeme, &*c.lexeme), ("a", "b", "c"));
+        assert_eq!(sexpr(value), "(1, +, 2)");
+        assert_eq!(value.span(), span(16, 20));
+        assert_eq!(statement("const a := b := 5"), "Const a := b := 5");
+    }
+
+    #[test]
+    fn parse_compound_assignment() {
+        assert_eq!(statement("x += 1"), "x := (x, +, 1)");
+        assert_eq!(statement("x -= 1 + 2"), "x := (x, -, (1, +, 2))");
+        assert_eq!(statement("x *= y"), "x := (x, *, y)");
+        assert_eq!(statement("x /= 2"), "x := (x, /, 2)");
+
+        let stmt = Parser::new(Scanner::new("x += 1")).next().unwrap();
+        let x = Token {
+            token_type: TokenType::Identifier("x".into()),
+            lexeme: "x".into(),
+            line: 1,
+            column: 1,
+        };
+        let plus = Token {
+            token_type: TokenType::Plus,
+            lexeme: "+".into(),
+            line: 1,
+            column: 3,
+        };
+        let expected = StmtKind::Assignment(
+            x.clone(),
+            Box::new(Expr::new(
+                ExprKind::Binary(
+                    Box::new(Expr::new(ExprKind::Var(x), span(1, 1))),
+                    plus,
+                    Box::new(Expr::new(ExprKind::Val(1), span(6, 6))),
+                ),
+                span(1, 6),
+            )),
+        );
+        assert_eq!(stmt, Stmt::new(expected, span(1, 6)));
+    }
+
+    #[test]
+    fn parse_compound_assignment_to_literal() {
+        let (_, errors) = Parser::new(Scanner::new("1 += 2")).parse_all();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] near '1': Can only assign to a variable."
+        );
+    }
+
+    #[test]
+    fn parse_sized_memory_access() {
+        assert_eq!(statement("store8(0, load16(1))"), "Store8(0, Load16(1))");
+        assert_eq!(
+            statement("store16(load8(0), load(1))"),
+            "Store16(Load8(0), Load(1))"
+        );
+    }
+
+    #[test]
+    fn parse_label() {
+        assert_eq!(statement("label loop:"), "Label loop");
+    }
+
+    #[test]
+    fn parse_goto_label() {
+        assert_eq!(statement("goto loop"), "Goto loop");
+    }
+
+    #[test]
+    fn parse_if_structurally() {
+        let mut parser = Parser::new(Scanner::new("if x then goto 2 else goto 3"));
+        let x = Token {
+            token_type: TokenType::Identifier("x".into()),
+            lexeme: "x".into(),
+            line: 1,
+            column: 4,
+        };
+        let expr = |kind, start_col, end_col| Box::new(Expr::new(kind, span(start_col, end_col)));
+        let goto = |target, start_col| {
+            let target = expr(ExprKind::Val(target), start_col + 5, start_col + 5);
+            Box::new(Stmt::new(
+                StmtKind::Goto(target),
+                span(start_col, start_col + 5),
+            ))
+        };
+        assert_eq!(
+            parser.next(),
+            Some(Stmt::new(
+                StmtKind::IfThenElse(expr(ExprKind::Var(x), 4, 4), goto(2, 11), goto(3, 23)),
+                span(1, 28)
+            ))
+        );
+        assert_eq!(parser.next(), None);
+    }
+
+    #[test]
+    fn into_program_rejects_trailing_tokens() {
+        let err = Parser::new(Scanner::new("x := 1 )"))
+            .into_program()
+            .unwrap_err();
+        assert_eq!(err.position(), (1, 8));
+        assert!(matches!(err, ParseError::Stmt(ref at, _) if at.lexeme == ")"));
+
+        let stmts = Parser::new(Scanner::new("x := 1; print x;"))
+            .into_program()
+            .unwrap();
+        let stmts: Vec<_> = stmts.iter().map(sexpr_stmt).collect();
+        assert_eq!(stmts, vec!["x := 1", "Print x"]);
+
+        let err = Parser::new(Scanner::new("print ) x := 1 #"))
+            .into_program()
+            .unwrap_err();
+        assert!(matches!(err, ParseError::Scan(_)));
+    }
+
+    #[test]
+    fn parse_all_sorts_errors() {
+        let (_, errors) = Parser::new(Scanner::new("x := 1 #\ny := 2\nz := )")).parse_all();
+        let positions: Vec<_> = errors.iter().map(ParseError::position).collect();
+        assert_eq!(positions, vec![(1, 8), (3, 6)]);
+        assert!(matches!(errors[0], ParseError::Scan(_)));
+        assert!(errors.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn parse_all_collects_errors() {
+        let (stmts, errors) =
+            Parser::new(Scanner::new("print ) print 1 store(1 2 print 3 @")).parse_all();
+        let stmts: Vec<_> = stmts.iter().map(sexpr_stmt).collect();
+        assert_eq!(stmts, vec!["Print 1", "Print 3"]);
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "[line 1] near ')': Expected Load, GetInput, Identifier, Value or '('.",
+                "[line 1] near '2': Expected ','.",
+                "[line 1, column 35] Invalid Token '@'",
+            ]
+        );
+    }
+
+    #[test]
+    fn two_token_lookahead() {
+        let mut parser = Parser::new(Scanner::new("x := 1"));
+        assert!(parser.at_assignment());
+        assert_eq!(
+            parser.scanner.peek_nth(2).map(|t| t.lexeme.as_str()),
+            Some("1")
+        );
+        assert_eq!(parser.scanner.peek_nth(3), None);
+        assert_eq!(sexpr_stmt(&parser.statement().unwrap()), "x := 1");
+
+        let mut parser = Parser::new(Scanner::new("x + 1"));
+        assert!(!parser.at_assignment());
+        assert_eq!(sexpr(&parser.expression().unwrap()), "(x, +, 1)");
+        assert!(parser.is_at_end());
+    }
+
+    #[test]
+    fn parse_recovers_before_assignment() {
+        let (stmts, errors) = Parser::new(Scanner::new("x := 1 +\ny := 2")).parse_all();
+        let stmts: Vec<_> = stmts.iter().map(sexpr_stmt).collect();
+        assert_eq!(stmts, vec!["y := 2"]);
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec!["[line 2] near 'y': Expected expression, found assignment."]
+        );
+
+        let (stmts, errors) =
+            Parser::new(Scanner::new("print ) ) z -= 1 print print 2")).parse_all();
+        let stmts: Vec<_> = stmts.iter().map(sexpr_stmt).collect();
+        assert_eq!(stmts, vec!["z := (z, -, 1)", "Print 2"]);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let incomplete = |src| {
+            let (_, errors) = Parser::new(Scanner::new(src)).parse_all();
+            errors.iter().any(ParseError::is_incomplete)
+        };
+        assert!(incomplete("store(1,"));
+        assert!(incomplete("if x then goto 1"));
+        assert!(incomplete("while 1 do print 1"));
+        assert!(incomplete("print \"abc"));
+        assert!(!incomplete("store(1, 2)"));
+        assert!(!incomplete("store(1, 2))"));
+        assert!(!incomplete("print )"));
+    }
+
+    #[test]
+    fn parse_stray_tokens() {
+        let src = ",".repeat(100_000);
+        assert_eq!(Parser::new(Scanner::new(&src)).count(), 0);
+        let src = format!("{} print 1", ", )".repeat(10_000));
+        assert_eq!(Parser::new(Scanner::new(&src)).count(), 1);
+    }
+
+    #[test]
+    fn parse_semicolons() {
+        let stmts: Vec<_> = Parser::new(Scanner::new("x := 1; y := 2"))
+            .map(|stmt| sexpr_stmt(&stmt))
+            .collect();
+        assert_eq!(stmts, vec!["x := 1", "y := 2"]);
+
+        let stmts: Vec<_> = Parser::new(Scanner::new(";x := 1;; { y := 2; };"))
+            .map(|stmt| sexpr_stmt(&stmt))
+            .collect();
+        assert_eq!(stmts, vec!["x := 1", "Block [y := 2]"]);
+    }
+
+    #[test]
+    fn parse_print() {
+        assert_eq!(statement("print 2 + 3"), "Print (2, +, 3)");
+    }
+
+    #[test]
+    fn parse_while() {
+        assert_eq!(
+            statement("while i < 3 do i := i + 1 print i end"),
+            "While (i, <, 3) Do [i := (i, +, 1), Print i] End"
+        );
+    }
+
+    #[test]
+    fn parse_call_return() {
+        assert_eq!(statement("call sub"), "Call sub");
+        assert_eq!(statement("return"), "Return");
+        assert_eq!(statement("halt"), "Halt");
+        assert_eq!(statement("nop"), "Nop");
+        assert_eq!(statement("dump"), "Dump");
+        assert_eq!(statement("const x := 1"), "Const x := 1");
+    }
+
+    #[test]
+    fn parse_expression_statement() {
+        let stmt = Parser::new(Scanner::new("1 + 2")).next().unwrap();
+        assert!(matches!(stmt.kind, StmtKind::Expression(_)));
+        assert_eq!(sexpr_stmt(&stmt), "(1, +, 2)");
+        assert_eq!(stmt.span(), span(1, 5));
+        assert_eq!(statement("x"), "x");
+        assert_eq!(statement("max(1, 2)"), "max(1, 2)");
+    }
+
+    #[test]
+    fn parse_block() {
+        assert_eq!(statement("{ x := 1 { } }"), "Block [x := 1, Block []]");
+    }
+
+    #[test]
+    fn parse_array() {
+        assert_eq!(expression("[1, 2 + 3, x]"), "[1, (2, +, 3), x]");
+        assert_eq!(expression("[]"), "[]");
+        assert_eq!(expression("[[1]]"), "[[1]]");
+    }
+
+    #[test]
+    fn parse_index() {
+        assert_eq!(expression("a[i + 1]"), "a[(i, +, 1)]");
+        assert_eq!(expression("[10, 20][1]"), "[10, 20][1]");
+        assert_eq!(
+            expression("-a[0] * b[1][2]"),
+            "(Unary(-, a[0]), *, b[1][2])"
+        );
+        assert_eq!(expression("(a + b)[0]"), "(a, +, b)[0]");
+
+        let expr = Parser::new(Scanner::new("a[0] + 1")).expression().unwrap();
+        let ExprKind::Binary(lhs, _, _) = &expr.kind else {
+            panic!("expected a binary expression, found {}", expr);
+        };
+        assert_eq!(lhs.span(), span(1, 4));
+    }
+
+    #[test]
+    fn parse_switch() {
+        assert_eq!(
+            statement("switch x + 1 { case 1: print 1 case 2: case 3: x := 1; print x default: }"),
+            "Switch (x, +, 1) [Case 1 [Print 1], Case 2 [], Case 3 [x := 1, Print x], Default []]"
+        );
+        assert_eq!(statement("switch x { }"), "Switch x []");
+    }
+
+    #[test]
+    fn parse_switch_errors() {
+        let errors = |src| {
+            let (_, errors) = Parser::new(Scanner::new(src)).parse_all();
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("switch x { case 1: print 1 case 1: print 2 } print 3"),
+            vec![
+                "[line 1] near '1': Duplicate case.",
+                "[line 1] near '}': Expected statement."
+            ]
+        );
+        assert_eq!(
+            errors("switch x { default: default: }"),
+            vec!["[line 1] near 'default': Duplicate default."]
+        );
+        assert_eq!(
+            errors("switch x { print 1 }"),
+            vec!["[line 1] near 'print': Expected case or default."]
+        );
+    }
+
+    #[test]
+    fn parse_unterminated_while() {
+        let err = Parser::new(Scanner::new("while 1 do x := 1"))
+            .statement()
+            .unwrap_err();
+        assert_eq!(
+            *err,
+            ParseError::Expected(
+                Location {
+                    line: 1,
+                    column: 18,
+                    lexeme: "end of input".into()
+                },
+                TokenType::End
+            )
+        );
+    }
+
     #[test]
     fn parse_load() {
         statement("goto load(1)");
@@ -370,16 +1625,234 @@ mod tests {
 
     #[test]
     fn parse_get_input() {
-        statement("goto get_input(stdout)");
+        assert_eq!(statement("goto get_input(stdin)"), "Goto GetInput(stdin)");
+        assert_eq!(statement("goto get_input(argv)"), "Goto GetInput(argv)");
+        assert_eq!(
+            statement("goto get_input(env, HOME)"),
+            "Goto GetInput(env, HOME)"
+        );
+
+        let expr = Parser::new(Scanner::new("get_input(argv) + 1"))
+            .expression()
+            .unwrap();
+        let ExprKind::Binary(lhs, _, _) = &expr.kind else {
+            panic!("expected a binary expression, found {}", expr);
+        };
+        assert_eq!(lhs.kind, ExprKind::GetInput("argv".into(), None));
+        assert_eq!(lhs.span(), span(1, 15));
+    }
+
+    #[test]
+    fn parse_get_input_unknown_channel() {
+        let error = |src| {
+            let (_, errors) = Parser::new(Scanner::new(src)).parse_all();
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            error("x := get_input(bogus)"),
+            vec!["[line 1] near 'bogus': Unknown input channel."]
+        );
+        assert_eq!(
+            error("x := get_input(stdout)"),
+            vec!["[line 1] near 'stdout': Unknown input channel."]
+        );
+        assert_eq!(
+            error("x := get_input(env)"),
+            vec!["[line 1] near ')': Expected ','."]
+        );
     }
 
     #[test]
     fn parse_precedence_1() {
-        assert_eq!(expression("1 * 1 + 1"), "((1, Star, 1), Plus, 1)");
+        assert_eq!(expression("1 * 1 + 1"), "((1, *, 1), +, 1)");
     }
 
     #[test]
     fn parse_precedence_2() {
-        assert_eq!(expression("1 + 1 * 1"), "(1, Plus, (1, Star, 1))");
+        assert_eq!(expression("1 + 1 * 1"), "(1, +, (1, *, 1))");
+    }
+
+    #[test]
+    fn parse_precedence_comparison() {
+        assert_eq!(expression("1 + 2 < 4"), "((1, +, 2), <, 4)");
+    }
+
+    #[test]
+    fn parse_precedence_logical() {
+        assert_eq!(
+            expression("1 < 2 or 3 == 4 and not 5"),
+            "((1, <, 2), or, ((3, ==, 4), and, Unary(not, 5)))"
+        );
+    }
+
+    #[test]
+    fn parse_precedence_bitwise() {
+        assert_eq!(
+            expression("1 | 2 ^ 3 & 4 == 4"),
+            "(1, |, (2, ^, (3, &, (4, ==, 4))))"
+        );
+        assert_eq!(expression("1 + 1 << 2 * 2"), "(1, +, (1, <<, (2, *, 2)))");
+    }
+
+    #[test]
+    fn parse_grouping() {
+        assert_eq!(expression("(1 + 2) * 3"), "((1, +, 2), *, 3)");
+        assert_eq!(expression("1 - (2 - 3)"), "(1, -, (2, -, 3))");
+        assert_eq!(expression("((1))"), "1");
+        assert_eq!(expression("-(1 + 2)"), "Unary(-, (1, +, 2))");
+    }
+
+    #[test]
+    fn parse_unclosed_grouping() {
+        let err = Parser::new(Scanner::new("(1 + (2 * 3)"))
+            .expression()
+            .unwrap_err();
+        assert_eq!(
+            *err,
+            ParseError::Expected(
+                Location {
+                    line: 1,
+                    column: 13,
+                    lexeme: "end of input".into()
+                },
+                TokenType::RightParen
+            )
+        );
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let mut parser = Parser::new(Scanner::new("x := 1\ny := )"));
+        parser.statement().unwrap();
+        let err = parser.statement().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 2] near ')': Expected Load, GetInput, Identifier, Value or '('."
+        );
+
+        let err = Parser::new(Scanner::new("\n\nstore(1 2)"))
+            .statement()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "[line 3] near '2': Expected ','.");
+    }
+
+    #[test]
+    fn parse_truncated_input() {
+        for src in ["x :=", "+", "x += -"] {
+            let err = Parser::new(Scanner::new(src)).statement().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "[line 1] near 'end of input': Expected token, found EOF.",
+                "{}",
+                src
+            );
+            assert!(err.is_incomplete());
+        }
+    }
+
+    #[test]
+    fn parse_precedence_equality() {
+        assert_eq!(expression("1 * 2 == 2"), "((1, *, 2), ==, 2)");
+    }
+
+    #[test]
+    fn parse_str_truncated() {
+        for src in [
+            "+",
+            "-",
+            "x :=",
+            "x := 1 +",
+            "load(",
+            "[1,",
+            "get_input(",
+            "x := 2 **",
+        ] {
+            let errors = crate::parse_str(src).unwrap_err();
+            assert!(errors.iter().all(ParseError::is_incomplete), "{}", src);
+        }
+        assert_eq!(crate::parse_str("x := 1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_str_deep_nesting() {
+        let deep =
+            |open: &str, close: &str| format!("{}1{}", open.repeat(100_000), close.repeat(100_000));
+        for src in [
+            format!("x := {}", deep("(", ")")),
+            format!("x := {}", deep("-", "")),
+            format!("x := {}", deep("2 ** ", "")),
+            format!("x := {}", deep("1 ? 1 : ", "")),
+            deep("{ ", " }"),
+            deep("while 1 do ", " end"),
+        ] {
+            let errors = crate::parse_str(&src).unwrap_err();
+            let too_deep = |err: &ParseError| err.to_string().ends_with("Nested too deeply.");
+            assert!(errors.iter().any(too_deep), "{}", &src[..20]);
+        }
+        let src = format!("x := {}1{}", "(".repeat(100), ")".repeat(100));
+        assert!(crate::parse_str(&src).is_ok());
+    }
+
+    #[test]
+    fn parse_str_arbitrary_input() {
+        // A fixed linear congruential generator, so failures can be reproduced.
+        let mut state: u32 = 1;
+        let pieces = [
+            "x",
+            ":=",
+            "+=",
+            "1",
+            "2.5",
+            "\"s",
+            "(",
+            ")",
+            "[",
+            "]",
+            "{",
+            "}",
+            ",",
+            ":",
+            ";",
+            "+",
+            "**",
+            "?",
+            "<",
+            "==",
+            "and",
+            "not",
+            "load",
+            "store8",
+            "get_input",
+            "env",
+            "goto",
+            "if",
+            "then",
+            "else",
+            "while",
+            "do",
+            "end",
+            "switch",
+            "case",
+            "default",
+            "label",
+            "call",
+            "return",
+            "halt",
+            "const",
+            "nop",
+            "max",
+            "$",
+            "\\",
+            "\n",
+        ];
+        for _ in 0..2000 {
+            let mut src = String::new();
+            for _ in 0..12 {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                src.push_str(pieces[(state >> 16) as usize % pieces.len()]);
+                src.push(' ');
+            }
+            let _ = crate::parse_str(&src);
+        }
     }
 }
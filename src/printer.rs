@@ -0,0 +1,239 @@
+use crate::parser::Parser;
+use crate::syntax::{Expr, ExprKind, Stmt, StmtKind};
+use crate::tokens::TokenType;
+use crate::visitor::Visitor;
+
+/// Renders a syntax tree back to canonical simpIL source,
+/// such that the output can be parsed again.
+#[derive(Debug, Default)]
+pub struct PrettyPrinter;
+
+impl PrettyPrinter {
+    /// Render every statement, one per line.
+    pub fn print(stmts: &[Stmt]) -> String {
+        let mut printer = Self;
+        stmts
+            .iter()
+            .map(|stmt| printer.visit_stmt(stmt))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Render the statements of a nested body, indented and one per line.
+    fn body(&mut self, stmts: &[Stmt]) -> String {
+        stmts
+            .iter()
+            .flat_map(|stmt| {
+                let stmt = self.visit_stmt(stmt);
+                stmt.lines()
+                    .map(|line| format!("  {}\n", line))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Render an operand, in parentheses if the parser would otherwise group it differently.
+    /// `needs_parens` receives the binding powers of a binary operand.
+    /// A unary operand binds as tightly as possible on its left.
+    /// A conditional operand always needs parentheses, as it binds looser than any operator.
+    fn operand(&mut self, e: &Expr, needs_parens: impl Fn(u8, u8) -> bool) -> String {
+        match &e.kind {
+            ExprKind::Binary(_, op, _) => match Parser::binary_binding_power(op) {
+                Ok((left, right)) if needs_parens(left, right) => {
+                    format!("({})", self.visit_expr(e))
+                }
+                _ => self.visit_expr(e),
+            },
+            ExprKind::Unary(op, _) => match Parser::prefix_binding_power(op) {
+                Ok(right) if needs_parens(u8::MAX, right) => format!("({})", self.visit_expr(e)),
+                _ => self.visit_expr(e),
+            },
+            ExprKind::Conditional(..) => format!("({})", self.visit_expr(e)),
+            _ => self.visit_expr(e),
+        }
+    }
+}
+
+impl Visitor<String> for PrettyPrinter {
+    fn visit_stmt(&mut self, s: &Stmt) -> String {
+        match &s.kind {
+            StmtKind::Assignment(var, expr) => format!("{} := {}", var, self.visit_expr(expr)),
+            StmtKind::Store(lhs, rhs, width) => format!(
+                "store{}({}, {})",
+                width.suffix(),
+                self.visit_expr(lhs),
+                self.visit_expr(rhs)
+            ),
+            StmtKind::Goto(target) => format!("goto {}", self.visit_expr(target)),
+            StmtKind::Assert(_, expr) => format!("assert {}", self.visit_expr(expr)),
+            StmtKind::IfThenElse(cond, iftrue, iffalse) => format!(
+                "if {} then {} else {}",
+                self.visit_expr(cond),
+                self.visit_stmt(iftrue),
+                self.visit_stmt(iffalse)
+            ),
+            StmtKind::Label(name) => format!("label {}:", name),
+            StmtKind::Print(expr) => format!("print {}", self.visit_expr(expr)),
+            StmtKind::Expression(expr) => self.visit_expr(expr),
+            StmtKind::While(cond, body) => {
+                format!("while {} do\n{}end", self.visit_expr(cond), self.body(body))
+            }
+            StmtKind::Block(body) => format!("{{\n{}}}", self.body(body)),
+            StmtKind::Call(target) => format!("call {}", self.visit_expr(target)),
+            StmtKind::Return => "return".into(),
+            StmtKind::Halt => "halt".into(),
+            StmtKind::Nop => "nop".into(),
+            StmtKind::Dump => "dump".into(),
+            StmtKind::ConstAssignment(var, expr) => {
+                format!("const {} := {}", var, self.visit_expr(expr))
+            }
+            StmtKind::Switch(expr, cases, default) => {
+                let mut branches: String = cases
+                    .iter()
+                    .map(|(value, body)| format!("case {}:\n{}", value, self.body(body)))
+                    .collect();
+                if let Some(body) = default {
+                    branches += &format!("default:\n{}", self.body(body));
+                }
+                format!("switch {} {{\n{}}}", self.visit_expr(expr), branches)
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, e: &Expr) -> String {
+        match &e.kind {
+            ExprKind::Load(reg, width) => {
+                format!("load{}({})", width.suffix(), self.visit_expr(reg))
+            }
+            ExprKind::Binary(lhs, op, rhs) => {
+                let (left, right) = Parser::binary_binding_power(op).unwrap_or((0, 0));
+                let lhs = self.operand(lhs, |_, lhs_right| lhs_right <= left);
+                let rhs = self.operand(rhs, |rhs_left, _| rhs_left < right);
+                format!("{} {} {}", lhs, op, rhs)
+            }
+            ExprKind::Unary(op, rhs) => {
+                let power = Parser::prefix_binding_power(op).unwrap_or(0);
+                let rhs = self.operand(rhs, |rhs_left, _| rhs_left < power);
+                if op.token_type == TokenType::Not {
+                    format!("{} {}", op, rhs)
+                } else {
+                    format!("{}{}", op, rhs)
+                }
+            }
+            ExprKind::Var(var) => var.lexeme.clone(),
+            ExprKind::GetInput(input, None) => format!("get_input({})", input),
+            ExprKind::GetInput(input, Some(key)) => format!("get_input({}, {})", input, key),
+            ExprKind::Val(val) => val.to_string(),
+            ExprKind::Float(val) => crate::tokens::float_literal(*val),
+            ExprKind::Str(val) => crate::tokens::quote(val),
+            ExprKind::Register(name) => format!("@{}", name),
+            ExprKind::Assign(var, value) => format!("{} := {}", var, self.visit_expr(value)),
+            ExprKind::Call(name, args) => {
+                let args: Vec<_> = args.iter().map(|arg| self.visit_expr(arg)).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            ExprKind::Array(items) => {
+                let items: Vec<_> = items.iter().map(|item| self.visit_expr(item)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            ExprKind::Index(array, index) => {
+                let array = self.operand(array, |_, _| true);
+                format!("{}[{}]", array, self.visit_expr(index))
+            }
+            ExprKind::Conditional(cond, iftrue, iffalse) => {
+                let cond = self.operand(cond, |_, _| false);
+                format!(
+                    "{} ? {} : {}",
+                    cond,
+                    self.visit_expr(iftrue),
+                    self.visit_expr(iffalse)
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn parse(src: &str) -> Vec<Stmt> {
+        Parser::new(Scanner::new(src)).collect()
+    }
+
+    fn round_trip(src: &str) {
+        let stmts = parse(src);
+        let printed = PrettyPrinter::print(&stmts);
+        assert_eq!(printed, src);
+        assert_eq!(format!("{:?}", parse(&printed)), format!("{:?}", stmts));
+    }
+
+    #[test]
+    fn round_trip_if_then_else() {
+        round_trip("if 1 then goto 2 else goto 3");
+        round_trip("if x then y := 1 else print 2");
+    }
+
+    #[test]
+    fn round_trip_chained_assignment() {
+        round_trip("a := b := 5 + c\nconst d := e := 1");
+    }
+
+    #[test]
+    fn round_trip_statements() {
+        round_trip("store(load(1), 2)\nassert -1\nlabel done:\nprint 1 + 2 * 3");
+        round_trip("call f\nreturn\nhalt\nconst x := 1");
+        round_trip("store8(load16(1), load8(2))\nstore16(0, 1)");
+        round_trip(r#"print "say \"hi\"\n" + "\t\\""#);
+        round_trip("assert not 0 and not 0 or 1");
+        round_trip("store(@r0, load(@eax) + 1)");
+    }
+
+    #[test]
+    fn round_trip_floats() {
+        round_trip("x := 1.5 + 2.0");
+        round_trip("x := 0.000001");
+        round_trip("x := 100000000000000000000.0");
+    }
+
+    #[test]
+    fn print_operators_as_source() {
+        let stmts = parse("x := !(1+2)*3 << y");
+        let printed = PrettyPrinter::print(&stmts);
+        assert_eq!(printed, "x := not (1 + 2) * 3 << y");
+        assert_eq!(parse(&printed)[0].to_string(), stmts[0].to_string());
+    }
+
+    #[test]
+    fn round_trip_while() {
+        round_trip("while i < 3 do\n  i := i + 1\n  while 0 do\n  end\nend");
+    }
+
+    #[test]
+    fn round_trip_array() {
+        round_trip("x := [1, 2 + 3, []][0]\ny := -a[i] * (a + b)[0]");
+    }
+
+    #[test]
+    fn round_trip_switch() {
+        round_trip("switch x {\ncase 1:\n  print 1\ncase 2:\ndefault:\n  x := 0\n  print x\n}");
+    }
+
+    #[test]
+    fn round_trip_block() {
+        round_trip("{\n  x := 1\n  {\n  }\n}");
+    }
+
+    #[test]
+    fn round_trip_grouping() {
+        round_trip("x := (1 + 2) * 3");
+        round_trip("x := 1 - (2 - 3) - 4");
+        round_trip("x := -(1 + 2) / (3 < 4)");
+        round_trip("x := (2 ** 3) ** 2 + 2 ** 3 ** 2");
+        round_trip("x := (-2) ** 2 - -2 ** 2");
+        round_trip("x := a or b ? 1 + 2 : c ? 3 : 4");
+        round_trip("x := -max(1, min(2, 3)) * abs(4)");
+        round_trip("x := (a ? b : c) ? d ? 1 : 2 : -(e ? 3 : 4)");
+    }
+}
@@ -1,13 +1,53 @@
 use std::fmt::{self, Display};
+use std::io::{self, Read};
 
 use crate::tokens::{Token, TokenType};
-use crate::Result;
+use crate::value::WordWidth;
+use thiserror::Error;
+
+/// An enum used for error reporting.
+///
+/// An `InvalidToken` or an `InvalidNumber` is reported and skipped, and scanning continues after it.
+/// An unterminated string cannot be recovered from, and ends the token stream,
+/// see `Scanner::failure`.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ScanError {
+    /// A character which does not begin any token.
+    #[error("[line {line}, column {column}] Invalid Token '{token}'")]
+    InvalidToken {
+        line: usize,
+        column: usize,
+        token: char,
+    },
+    /// A numeric literal which is malformed or does not fit in the word width.
+    #[error("[line {line}, column {column}] Invalid number '{lexeme}'")]
+    InvalidNumber {
+        line: usize,
+        column: usize,
+        lexeme: String,
+    },
+    /// A string literal without a closing quote.
+    #[error("[line {line}, column {column}] Unterminated string")]
+    UnterminatedString { line: usize, column: usize },
+}
+
+impl ScanError {
+    /// The line and column at which the offending token starts.
+    pub fn position(&self) -> (usize, usize) {
+        match *self {
+            ScanError::InvalidToken { line, column, .. }
+            | ScanError::InvalidNumber { line, column, .. }
+            | ScanError::UnterminatedString { line, column } => (line, column),
+        }
+    }
+}
 
 /// The Scanner turns a stream of bytes into [`Token`](tokens/struct.Token.html)s.
 ///
 /// Example use:
 ///
 /// ```
+/// # use simpilrs::Scanner;
 /// let input = "goto 1";
 /// let tokens: Vec<_> =
 ///     Scanner::new(input)
@@ -24,27 +64,35 @@ pub struct Scanner {
     current: usize,
     line: usize,
     column: usize,
+    errors: Vec<ScanError>,
+    failure: Option<ScanError>,
+    max_value: u32,
 }
 
 impl Display for Scanner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "[")?;
-        let tokens: Vec<_> = self.clone().map(|token| format!("{}", token)).collect();
+        let tokens: Vec<_> = self
+            .clone()
+            .map(|token| format!("{:?}", token.token_type))
+            .collect();
         write!(f, "{}", tokens.join(","))?;
         write!(f, "]")
     }
 }
 
+/// Yields the valid tokens, skipping invalid ones, which are collected in `errors`.
+/// Stops at the end of the source, or at the first unrecoverable error, which is kept in `failure`.
 impl Iterator for Scanner {
     type Item = Token;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.is_at_end() {
-            None
-        } else {
-            match self.scan_token() {
-                Ok(t) => Some(t),
-                Err(_) => None,
+        match self.scan_token() {
+            Ok(token) => token,
+            Err(err) => {
+                self.current = self.source.len();
+                self.failure = Some(err);
+                None
             }
         }
     }
@@ -54,59 +102,226 @@ impl Scanner {
     /// Construct an instance of the Scanner.
     /// The string reference is turned into bytes internally.
     pub fn new(source: &str) -> Self {
+        Self::from_bytes(source.as_bytes().to_owned())
+    }
+
+    /// Construct a Scanner over raw bytes, which need not be valid UTF-8.
+    /// Bytes which do not form a valid character are reported as invalid tokens.
+    pub fn from_bytes(source: Vec<u8>) -> Self {
         Self {
-            source: source.as_bytes().to_owned(),
+            source,
             start: 0,
             current: 0,
             line: 1,
             column: 0,
+            errors: Vec::new(),
+            failure: None,
+            max_value: WordWidth::default().max(),
         }
     }
 
+    /// Construct a Scanner over everything `reader` produces, see `from_bytes`.
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut source = Vec::new();
+        reader.read_to_end(&mut source)?;
+        Ok(Self::from_bytes(source))
+    }
+
+    /// Construct a Scanner which rejects integer literals wider than `width`.
+    pub fn with_word_width(source: &str, width: WordWidth) -> Self {
+        Self {
+            max_value: width.max(),
+            ..Self::new(source)
+        }
+    }
+
+    /// The errors reported so far.
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    /// The error which ended the token stream early, if any.
+    pub fn failure(&self) -> Option<&ScanError> {
+        self.failure.as_ref()
+    }
+
+    /// Scan the rest of the source without consuming this scanner,
+    /// collecting every token and every error.
+    pub fn scan_all(&self) -> (Vec<Token>, Vec<ScanError>) {
+        let mut scanner = self.clone();
+        let tokens = scanner.by_ref().collect();
+        let mut errors = scanner.errors;
+        errors.extend(scanner.failure);
+        (tokens, errors)
+    }
+
     /// Returns the next token, skipping invalid tokens and whitespace.
-    fn scan_token(&mut self) -> Result<Token> {
+    /// Returns `None` once the source is exhausted.
+    fn scan_token(&mut self) -> Result<Option<Token>, ScanError> {
         loop {
+            if self.is_at_end() {
+                return Ok(None);
+            }
+            self.start = self.current;
             let c = self.advance();
+            let column = self.column;
             let token_type = match c {
                 b'(' => TokenType::LeftParen,
                 b')' => TokenType::RightParen,
+                b'{' => TokenType::LeftBrace,
+                b'}' => TokenType::RightBrace,
+                b'[' => TokenType::LeftBracket,
+                b']' => TokenType::RightBracket,
                 b',' => TokenType::Comma,
-                b'+' => TokenType::Plus,
-                b'-' => TokenType::Minus,
-                b'*' => TokenType::Star,
-                b'/' => TokenType::Slash,
+                b';' => TokenType::Semicolon,
+                b'+' => {
+                    if self.matches(b'=') {
+                        TokenType::PlusEqual
+                    } else {
+                        TokenType::Plus
+                    }
+                }
+                b'-' => {
+                    if self.matches(b'=') {
+                        TokenType::MinusEqual
+                    } else {
+                        TokenType::Minus
+                    }
+                }
+                b'*' => {
+                    if self.matches(b'*') {
+                        TokenType::StarStar
+                    } else if self.matches(b'=') {
+                        TokenType::StarEqual
+                    } else {
+                        TokenType::Star
+                    }
+                }
+                b'/' => {
+                    if self.matches(b'=') {
+                        TokenType::SlashEqual
+                    } else {
+                        TokenType::Slash
+                    }
+                }
+                b'%' => TokenType::Percent,
+                b'&' => TokenType::Ampersand,
+                b'|' => TokenType::Pipe,
+                b'^' => TokenType::Caret,
+                b'?' => TokenType::Question,
                 b':' => {
                     if self.matches(b'=') {
                         TokenType::Assign
                     } else {
-                        TokenType::Invalid(c)
+                        TokenType::Colon
                     }
                 }
-                b' ' | b'\r' | b'\t' => {
-                    self.start += 1;
-                    TokenType::Ignore
+                b'<' => {
+                    if self.matches(b'=') {
+                        TokenType::LessEqual
+                    } else if self.matches(b'<') {
+                        TokenType::LessLess
+                    } else {
+                        TokenType::Less
+                    }
+                }
+                b'>' => {
+                    if self.matches(b'=') {
+                        TokenType::GreaterEqual
+                    } else if self.matches(b'>') {
+                        TokenType::GreaterGreater
+                    } else {
+                        TokenType::Greater
+                    }
                 }
+                b'=' => {
+                    if self.matches(b'=') {
+                        TokenType::EqualEqual
+                    } else {
+                        TokenType::Invalid('=')
+                    }
+                }
+                b'!' => {
+                    if self.matches(b'=') {
+                        TokenType::BangEqual
+                    } else {
+                        TokenType::Not
+                    }
+                }
+                b' ' | b'\r' | b'\t' => TokenType::Ignore,
                 b'\n' => {
                     self.line += 1;
-                    self.column = 1;
-                    self.start = 0;
+                    self.column = 0;
                     TokenType::Ignore
                 }
+                // A backslash at the end of a line continues the line.
+                b'\\' if self.matches(b'\n') => {
+                    self.line += 1;
+                    self.column = 0;
+                    TokenType::Ignore
+                }
+                b'"' => self.string(column)?,
                 b'0'..=b'9' => {
-                    let mut nums = vec![c];
+                    let radix = match (c, self.peek()) {
+                        (b'0', b'x') => 16,
+                        (b'0', b'b') => 2,
+                        _ => 10,
+                    };
+                    let mut nums = if radix == 10 {
+                        vec![c]
+                    } else {
+                        self.advance();
+                        Vec::new()
+                    };
                     loop {
                         let next = self.peek();
                         match next {
-                            b'0'..=b'9' => {
+                            b'0'..=b'9' | b'_' => {
+                                nums.push(next);
+                                self.advance();
+                            }
+                            b'a'..=b'z' | b'A'..=b'Z' if radix != 10 => {
                                 nums.push(next);
                                 self.advance();
                             }
                             _ => break,
                         }
                     }
-                    let nums: &str = &String::from_utf8(nums)?;
-                    let nums: u32 = str::parse(nums)?;
-                    TokenType::Value(nums)
+                    let separated = nums.first() != Some(&b'_')
+                        && nums.last() != Some(&b'_')
+                        && !nums.windows(2).any(|pair| pair == b"__");
+                    nums.retain(|&c| c != b'_');
+                    let is_float =
+                        radix == 10 && self.peek() == b'.' && self.peek_next().is_ascii_digit();
+                    if is_float {
+                        nums.push(self.advance());
+                        while self.peek().is_ascii_digit() {
+                            nums.push(self.advance());
+                        }
+                    }
+                    let nums = String::from_utf8_lossy(&nums);
+                    let parsed = if !separated {
+                        None
+                    } else if is_float {
+                        nums.parse().map(TokenType::Float).ok()
+                    } else {
+                        u32::from_str_radix(&nums, radix)
+                            .ok()
+                            .filter(|&v| v <= self.max_value)
+                            .map(TokenType::Value)
+                    };
+                    match parsed {
+                        Some(token_type) => token_type,
+                        None => self.invalid_number(column),
+                    }
+                }
+                // Digits led by a separator, such as `_1`, are a malformed number
+                // rather than an identifier.
+                b'_' if self.separated_digits() => {
+                    while matches!(self.peek(), b'0'..=b'9' | b'_') {
+                        self.advance();
+                    }
+                    self.invalid_number(column)
                 }
                 b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
                     let mut ident = vec![c];
@@ -117,43 +332,130 @@ impl Scanner {
                                 ident.push(next);
                                 self.advance();
                             }
+                            // A namespaced name such as `math.pi`. A dot which is not
+                            // followed by another part is left to be reported as invalid.
+                            b'.' if matches!(self.peek_next(), b'a'..=b'z' | b'A'..=b'Z' | b'_') => {
+                                ident.push(next);
+                                self.advance();
+                            }
                             _ => break,
                         };
                     }
-                    let ident = String::from_utf8(ident)?;
+                    let ident = String::from_utf8_lossy(&ident).into_owned();
                     match ident.as_ref() {
                         "store" => TokenType::Store,
+                        "store8" => TokenType::Store8,
+                        "store16" => TokenType::Store16,
                         "goto" => TokenType::Goto,
                         "assert" => TokenType::Assert,
                         "if" => TokenType::If,
                         "then" => TokenType::Then,
                         "else" => TokenType::Else,
                         "load" => TokenType::Load,
+                        "load8" => TokenType::Load8,
+                        "load16" => TokenType::Load16,
                         "get_input" => TokenType::GetInput,
+                        "label" => TokenType::Label,
+                        "print" => TokenType::Print,
+                        "while" => TokenType::While,
+                        "do" => TokenType::Do,
+                        "end" => TokenType::End,
+                        "call" => TokenType::Call,
+                        "return" => TokenType::Return,
+                        "halt" => TokenType::Halt,
+                        "const" => TokenType::Const,
+                        "switch" => TokenType::Switch,
+                        "case" => TokenType::Case,
+                        "default" => TokenType::Default,
+                        "nop" => TokenType::Nop,
+                        "dump" => TokenType::Dump,
+                        "and" => TokenType::And,
+                        "or" => TokenType::Or,
+                        "not" => TokenType::Not,
+                        "true" => TokenType::Value(1),
+                        "false" => TokenType::Value(0),
                         _ => TokenType::Identifier(ident),
                     }
                 }
-                _ => TokenType::Invalid(c),
+                b'@' if matches!(self.peek(), b'a'..=b'z' | b'A'..=b'Z' | b'_') => {
+                    let mut name = Vec::new();
+                    while let next @ (b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'0'..=b'9') = self.peek()
+                    {
+                        name.push(next);
+                        self.advance();
+                    }
+                    TokenType::Register(String::from_utf8_lossy(&name).into_owned())
+                }
+                c if c.is_ascii() => TokenType::Invalid(c as char),
+                _ => TokenType::Invalid(self.utf8_char(c)),
             };
 
             match token_type {
                 TokenType::Ignore => (),
-                TokenType::Invalid(c) => crate::report(
-                    self.line,
-                    self.column,
-                    &format!("Invalid Token '{}'", c as char),
-                ),
+                TokenType::Invalid(c) => {
+                    self.errors.push(ScanError::InvalidToken {
+                        line: self.line,
+                        column,
+                        token: c,
+                    });
+                }
                 _ => {
                     let lexeme = self.source[self.start..self.current].to_owned();
-                    self.start = self.current;
-                    return Ok(Token {
+                    return Ok(Some(Token {
                         token_type,
-                        lexeme: String::from_utf8(lexeme)?,
+                        lexeme: String::from_utf8_lossy(&lexeme).into_owned(),
                         line: self.line,
-                    });
+                        column,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Scans the rest of a string literal, after its opening quote.
+    /// Supports the escapes `\n`, `\t`, `\"` and `\\`.
+    fn string(&mut self, column: usize) -> Result<TokenType, ScanError> {
+        let line = self.line;
+        let mut text = Vec::new();
+        loop {
+            if self.is_at_end() {
+                return Err(ScanError::UnterminatedString { line, column });
+            }
+            match self.advance() {
+                b'"' => break,
+                b'\\' if !self.is_at_end() => match self.advance() {
+                    b'n' => text.push(b'\n'),
+                    b't' => text.push(b'\t'),
+                    c => text.push(c),
+                },
+                b'\n' => {
+                    self.line += 1;
+                    self.column = 0;
+                    text.push(b'\n');
                 }
+                c => text.push(c),
             }
         }
+        Ok(TokenType::Str(String::from_utf8_lossy(&text).into_owned()))
+    }
+
+    /// Decodes the rest of a multi-byte UTF-8 character, given its leading byte.
+    /// The character counts as a single column.
+    /// Malformed sequences decode to `char::REPLACEMENT_CHARACTER`.
+    fn utf8_char(&mut self, lead: u8) -> char {
+        let len = match lead {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        while self.current - self.start < len && self.peek() & 0xC0 == 0x80 {
+            self.current += 1;
+        }
+        std::str::from_utf8(&self.source[self.start..self.current])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
     }
 
     /// True of the current character matches the input.
@@ -169,13 +471,42 @@ impl Scanner {
 
     /// True if we've reached the end of the source code.
     fn is_at_end(&self) -> bool {
-        self.current as usize >= self.source.len()
+        self.current >= self.source.len()
+    }
+
+    /// Report the literal scanned so far as an invalid number, which is then skipped.
+    fn invalid_number(&mut self, column: usize) -> TokenType {
+        let lexeme = &self.source[self.start..self.current];
+        self.errors.push(ScanError::InvalidNumber {
+            line: self.line,
+            column,
+            lexeme: String::from_utf8_lossy(lexeme).into_owned(),
+        });
+        TokenType::Ignore
+    }
+
+    /// Whether the rest of the word after a `_` is only digits and separators,
+    /// including at least one digit, such as the `1` of `_1`.
+    fn separated_digits(&self) -> bool {
+        let rest = &self.source[self.current..];
+        let len = rest
+            .iter()
+            .take_while(|c| c.is_ascii_digit() || **c == b'_')
+            .count();
+        let is_word = |c: Option<&u8>| matches!(c, Some(b'a'..=b'z' | b'A'..=b'Z' | b'_'));
+        let continues = match rest.get(len) {
+            Some(b'a'..=b'z' | b'A'..=b'Z') => true,
+            Some(b'.') => is_word(rest.get(len + 1)),
+            _ => false,
+        };
+        rest[..len].iter().any(u8::is_ascii_digit) && !continues
     }
 
-    /// Returns the next character and increments the counter.
+    /// Returns the next character and increments the counter and column.
     fn advance(&mut self) -> u8 {
         let char = self.source[self.current];
         self.current += 1;
+        self.column += 1;
         char
     }
 
@@ -187,6 +518,11 @@ impl Scanner {
             self.source[self.current]
         }
     }
+
+    /// Returns the character after the next one without advancing.
+    fn peek_next(&self) -> u8 {
+        self.source.get(self.current + 1).copied().unwrap_or(b'\0')
+    }
 }
 
 #[cfg(test)]
@@ -211,10 +547,394 @@ mod tests {
         assert_eq!(lex("val := 2"), r#"[Identifier("val"),Assign,Value(2)]"#)
     }
 
+    #[test]
+    fn scan_arithmetic() {
+        assert_eq!(
+            lex("+ - * / % **"),
+            "[Plus,Minus,Star,Slash,Percent,StarStar]"
+        )
+    }
+
+    #[test]
+    fn scan_label() {
+        assert_eq!(lex("label done:"), r#"[Label,Identifier("done"),Colon]"#)
+    }
+
+    #[test]
+    fn scan_comparisons() {
+        assert_eq!(
+            lex("< <= > >= == !="),
+            "[Less,LessEqual,Greater,GreaterEqual,EqualEqual,BangEqual]"
+        )
+    }
+
+    #[test]
+    fn scan_bitwise() {
+        assert_eq!(
+            lex("& | ^ << >> < >"),
+            "[Ampersand,Pipe,Caret,LessLess,GreaterGreater,Less,Greater]"
+        )
+    }
+
+    #[test]
+    fn scan_lone_equal_is_invalid() {
+        assert_eq!(lex("1 = 2"), "[Value(1),Value(2)]")
+    }
+
+    #[test]
+    fn scan_while() {
+        assert_eq!(lex("while 1 do end"), "[While,Value(1),Do,End]")
+    }
+
+    #[test]
+    fn scan_switch() {
+        assert_eq!(
+            lex("switch x { case 1: default: }"),
+            r#"[Switch,Identifier("x"),LeftBrace,Case,Value(1),Colon,Default,Colon,RightBrace]"#
+        )
+    }
+
+    #[test]
+    fn scan_registers() {
+        assert_eq!(
+            lex("@r0 @eax_1 @ 1"),
+            r#"[Register("r0"),Register("eax_1"),Value(1)]"#
+        )
+    }
+
+    #[test]
+    fn scan_call_return() {
+        assert_eq!(
+            lex("call f return halt const nop dump"),
+            r#"[Call,Identifier("f"),Return,Halt,Const,Nop,Dump]"#
+        )
+    }
+
+    #[test]
+    fn scan_strings() {
+        assert_eq!(lex(r#""hello" "a b""#), r#"[Str("hello"),Str("a b")]"#);
+        assert_eq!(
+            lex(r#""line\n\ttab \"quoted\" back\\slash""#),
+            r#"[Str("line\n\ttab \"quoted\" back\\slash")]"#
+        );
+    }
+
+    #[test]
+    fn scan_unterminated_string() {
+        let mut scanner = Scanner::new("x := \"abc");
+        assert_eq!(scanner.by_ref().count(), 2);
+        assert_eq!(
+            scanner.failure(),
+            Some(&ScanError::UnterminatedString { line: 1, column: 6 })
+        );
+    }
+
+    #[test]
+    fn scan_semicolon() {
+        assert_eq!(
+            lex("x := 1;"),
+            r#"[Identifier("x"),Assign,Value(1),Semicolon]"#
+        )
+    }
+
+    #[test]
+    fn scan_braces() {
+        assert_eq!(lex("{ }"), "[LeftBrace,RightBrace]")
+    }
+
+    #[test]
+    fn scan_logical() {
+        assert_eq!(lex("and or not !"), "[And,Or,Not,Not]")
+    }
+
     #[test]
     fn proper_lexemes_assignment() {
         let actual = flex("val := 1");
-        let expected = r#"[Token { token_type: Identifier("val"), lexeme: "val", line: 1 }, Token { token_type: Assign, lexeme: ":=", line: 1 }, Token { token_type: Value(1), lexeme: "1", line: 1 }]"#;
+        let expected = r#"[Token { token_type: Identifier("val"), lexeme: "val", line: 1, column: 1 }, Token { token_type: Assign, lexeme: ":=", line: 1, column: 5 }, Token { token_type: Value(1), lexeme: "1", line: 1, column: 8 }]"#;
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn scan_number_overflow() {
+        let mut scanner = Scanner::new("x := 4294967296 y := 1");
+        assert_eq!(scanner.by_ref().count(), 5);
+        assert_eq!(
+            scanner.errors(),
+            &[ScanError::InvalidNumber {
+                line: 1,
+                column: 6,
+                lexeme: "4294967296".into()
+            }]
+        );
+        assert_eq!(scanner.failure(), None);
+        assert_eq!(lex("4294967295"), "[Value(4294967295)]");
+    }
+
+    #[test]
+    fn scan_word_width() {
+        let scan_u8 = |src| {
+            let mut scanner = Scanner::with_word_width(src, WordWidth::U8);
+            let tokens: Vec<_> = scanner.by_ref().map(|t| t.token_type).collect();
+            (tokens, scanner.errors().to_vec())
+        };
+        assert_eq!(scan_u8("255"), (vec![TokenType::Value(255)], vec![]));
+        assert_eq!(
+            scan_u8("256"),
+            (
+                vec![],
+                vec![ScanError::InvalidNumber {
+                    line: 1,
+                    column: 1,
+                    lexeme: "256".into()
+                }]
+            )
+        );
+    }
+
+    #[test]
+    fn scan_radix_literals() {
+        assert_eq!(
+            lex("0xFF 0xff 0b101 0 07"),
+            "[Value(255),Value(255),Value(5),Value(0),Value(7)]"
+        );
+        assert_eq!(lex("0xFFFFFFFF"), "[Value(4294967295)]");
+    }
+
+    #[test]
+    fn scan_invalid_radix_literals() {
+        for (src, lexeme) in &[
+            ("0x", "0x"),
+            ("0b102", "0b102"),
+            ("0x100000000", "0x100000000"),
+        ] {
+            let mut scanner = Scanner::new(src);
+            assert_eq!(scanner.by_ref().count(), 0);
+            assert_eq!(
+                scanner.errors(),
+                &[ScanError::InvalidNumber {
+                    line: 1,
+                    column: 1,
+                    lexeme: lexeme.to_string()
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn scan_digit_separators() {
+        assert_eq!(
+            lex("1_000 1_000_000 0xFF_FF 1_0.25"),
+            "[Value(1000),Value(1000000),Value(65535),Float(10.25)]"
+        );
+        assert_eq!(lex("_ _x"), r#"[Identifier("_"),Identifier("_x")]"#);
+    }
+
+    #[test]
+    fn scan_invalid_digit_separators() {
+        for src in &["1__0", "_1", "1_", "0x_F", "1_.5"] {
+            let mut scanner = Scanner::new(src);
+            assert_eq!(scanner.by_ref().count(), 0, "{}", src);
+            assert_eq!(
+                scanner.errors(),
+                &[ScanError::InvalidNumber {
+                    line: 1,
+                    column: 1,
+                    lexeme: src.to_string()
+                }]
+            );
+        }
+        assert_eq!(lex("_1a _1.x"), r#"[Identifier("_1a"),Identifier("_1.x")]"#);
+        let (tokens, errors) = Scanner::new("x := 1__0 print 2 y := _1").scan_all();
+        assert_eq!(tokens.len(), 6);
+        let lexemes: Vec<_> = errors
+            .iter()
+            .map(|err| match err {
+                ScanError::InvalidNumber { lexeme, .. } => lexeme.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(lexemes, vec!["1__0", "_1"]);
+    }
+
+    #[test]
+    fn scan_floats() {
+        assert_eq!(lex("1.5 0.25 3"), "[Float(1.5),Float(0.25),Value(3)]");
+        assert_eq!(lex("1."), "[Value(1)]");
+    }
+
+    #[test]
+    fn scan_all_collects_errors() {
+        let (tokens, errors) = Scanner::new("x @ # y").scan_all();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(
+            errors,
+            vec![
+                ScanError::InvalidToken {
+                    line: 1,
+                    column: 3,
+                    token: '@'
+                },
+                ScanError::InvalidToken {
+                    line: 1,
+                    column: 5,
+                    token: '#'
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_character() {
+        let mut scanner = Scanner::new("x é y");
+        assert_eq!(scanner.by_ref().count(), 2);
+        assert_eq!(
+            scanner.errors(),
+            &[ScanError::InvalidToken {
+                line: 1,
+                column: 3,
+                token: 'é'
+            }]
+        );
+
+        let mut scanner = Scanner::new("\u{1F600}@");
+        assert_eq!(scanner.by_ref().count(), 0);
+        let tokens: Vec<_> = scanner.errors().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                "[line 1, column 1] Invalid Token '\u{1F600}'",
+                "[line 1, column 2] Invalid Token '@'"
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_compound_assignment() {
+        assert_eq!(
+            lex("x += 1 -= 2 *= 3 /= 4 ** 5"),
+            "[Identifier(\"x\"),PlusEqual,Value(1),MinusEqual,Value(2),StarEqual,Value(3),SlashEqual,Value(4),StarStar,Value(5)]"
+        );
+    }
+
+    #[test]
+    fn scan_bytes() {
+        let scanner = Scanner::from_bytes(b"x := 1 print x".to_vec());
+        assert_eq!(
+            format!("{}", scanner),
+            r#"[Identifier("x"),Assign,Value(1),Print,Identifier("x")]"#
+        );
+
+        let reader = io::Cursor::new(b"store(1, 2)".to_vec());
+        let scanner = Scanner::from_reader(reader).unwrap();
+        assert_eq!(
+            format!("{}", scanner),
+            "[Store,LeftParen,Value(1),Comma,Value(2),RightParen]"
+        );
+    }
+
+    #[test]
+    fn scan_invalid_utf8() {
+        let mut scanner = Scanner::from_bytes(b"x \xff\xfe y".to_vec());
+        assert_eq!(scanner.by_ref().count(), 2);
+        let invalid = |column| ScanError::InvalidToken {
+            line: 1,
+            column,
+            token: char::REPLACEMENT_CHARACTER,
+        };
+        assert_eq!(scanner.errors(), &[invalid(3), invalid(4)]);
+    }
+
+    #[test]
+    fn scan_past_invalid_token() {
+        let mut scanner = Scanner::new("x := 1 @ print x");
+        assert_eq!(
+            scanner
+                .by_ref()
+                .map(|token| token.token_type)
+                .collect::<Vec<_>>(),
+            vec![
+                TokenType::Identifier("x".into()),
+                TokenType::Assign,
+                TokenType::Value(1),
+                TokenType::Print,
+                TokenType::Identifier("x".into()),
+            ]
+        );
+        assert_eq!(scanner.errors().len(), 1);
+        assert_eq!(scanner.failure(), None);
+
+        let mut scanner = Scanner::new("x := 99999999999 @ print x");
+        assert_eq!(scanner.by_ref().count(), 4);
+        assert_eq!(scanner.errors().len(), 2);
+        assert!(matches!(
+            scanner.errors()[0],
+            ScanError::InvalidNumber { .. }
+        ));
+        assert_eq!(scanner.failure(), None);
+    }
+
+    #[test]
+    fn scan_booleans() {
+        assert_eq!(
+            lex("assert true false truth"),
+            r#"[Assert,Value(1),Value(0),Identifier("truth")]"#
+        );
+    }
+
+    #[test]
+    fn scan_line_continuation() {
+        assert_eq!(lex("1 + \\\n2"), "[Value(1),Plus,Value(2)]");
+
+        let tokens: Vec<_> = Scanner::new("x := \\\n  y").collect();
+        assert_eq!((tokens[2].line, tokens[2].column), (2, 3));
+
+        let mut scanner = Scanner::new("1 \\ 2");
+        assert_eq!(scanner.by_ref().count(), 2);
+        assert_eq!(
+            scanner.errors(),
+            &[ScanError::InvalidToken {
+                line: 1,
+                column: 3,
+                token: '\\'
+            }]
+        );
+    }
+
+    #[test]
+    fn scan_dotted_identifiers() {
+        assert_eq!(lex("foo.bar"), r#"[Identifier("foo.bar")]"#);
+        assert_eq!(
+            lex("math.pi := a.b_2.c"),
+            r#"[Identifier("math.pi"),Assign,Identifier("a.b_2.c")]"#
+        );
+
+        for (src, column) in [("foo.", 4), (".foo", 1), ("foo..bar", 4), ("foo.1", 4)] {
+            let mut scanner = Scanner::new(src);
+            assert!(scanner.by_ref().all(|token| !token.lexeme.contains('.')));
+            assert_eq!(
+                scanner.errors().first(),
+                Some(&ScanError::InvalidToken {
+                    line: 1,
+                    column,
+                    token: '.'
+                }),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_token_column() {
+        let mut scanner = Scanner::new("  @");
+        assert_eq!(scanner.by_ref().count(), 0);
+        assert_eq!(
+            scanner.errors(),
+            &[ScanError::InvalidToken {
+                line: 1,
+                column: 3,
+                token: '@'
+            }]
+        );
+    }
 }
@@ -1,75 +1,220 @@
+use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display};
 
+use crate::printer::PrettyPrinter;
 use crate::tokens::Token;
-
-/// A program is 1 or more statements.
-#[derive(Debug)]
-struct Program {
-    statements: Vec<Stmt>,
-}
+use crate::value::WordWidth;
+use crate::visitor::Visitor;
 
 #[doc(hidden)]
 type BoxExpr = Box<Expr>;
 
+/// A region of source code, from the first character of a node to its last, inclusive.
+/// Lines and columns count from 1, like those of a `Token`.
+/// Nodes built by hand rather than parsed have the default, empty span.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl Span {
+    /// The span of a single token.
+    pub fn of(token: &Token) -> Self {
+        // A token's line is where it ends, which only differs for strings spanning lines.
+        let lines = token.lexeme.matches('\n').count();
+        let end_col = match token.lexeme.rfind('\n') {
+            Some(newline) => token.lexeme[newline + 1..].chars().count(),
+            None => token.column + token.lexeme.chars().count().max(1) - 1,
+        };
+        Self {
+            start_line: token.line - lines,
+            start_col: token.column,
+            end_line: token.line,
+            end_col,
+        }
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            end_line: other.end_line,
+            end_col: other.end_col,
+            ..self
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}-{}:{}",
+            self.start_line, self.start_col, self.end_line, self.end_col
+        )
+    }
+}
+
+/// A statement, along with the source code it was parsed from.
+/// Serialized as its kind alone; deserialized statements have the default span.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl Stmt {
+    /// Create a statement parsed from `span`.
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The source code this statement was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// True if the value of this statement is one of the results of a run,
+    /// i.e. it is an assignment, a print or an expression.
+    /// Jumps, stores and other statements run only for their effect.
+    pub fn produces_value(&self) -> bool {
+        matches!(
+            self.kind,
+            StmtKind::Assignment(..)
+                | StmtKind::ConstAssignment(..)
+                | StmtKind::Print(_)
+                | StmtKind::Expression(_)
+        )
+    }
+}
+
+impl From<StmtKind> for Stmt {
+    fn from(kind: StmtKind) -> Self {
+        Self::new(kind, Span::default())
+    }
+}
+
+/// An expression, along with the source code it was parsed from.
+/// Serialized as its kind alone; deserialized expressions have the default span.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Expr {
+    pub kind: ExprKind,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl Expr {
+    /// Create an expression parsed from `span`.
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// The source code this expression was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Self {
+        Self::new(kind, Span::default())
+    }
+}
+
 /// Statements perform side effects.
-#[derive(Debug, Clone)]
-pub enum Stmt {
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StmtKind {
     /// Assign a value to a variable.
     Assignment(Token, BoxExpr),
     /// Store a value in a register.
-    Store(BoxExpr, BoxExpr),
+    /// Only the low bits of the value are stored, according to the width.
+    Store(BoxExpr, BoxExpr, WordWidth),
     /// Resume program execution on the line indicated.
     Goto(BoxExpr),
     /// A normal assertion. Accepts `true` (1) and `false` (0).
-    Assert(BoxExpr),
-    /// An if statement. Accepts `true` (1) and `false` (0).
-    IfThenElse(BoxExpr, BoxExpr, BoxExpr),
+    /// The token of the `assert` keyword locates failures.
+    Assert(Token, BoxExpr),
+    /// An if statement, executing the first statement if the condition is `true` (1)
+    /// and the second if it is `false` (0). Usually both are a `Goto`.
+    IfThenElse(BoxExpr, Box<Stmt>, Box<Stmt>),
+    /// Name the position of this statement, so it can be the target of a goto.
+    Label(String),
+    /// Write the value of an expression to the output.
+    Print(BoxExpr),
+    /// Execute the body for as long as the condition is nonzero.
+    While(BoxExpr, Vec<Stmt>),
+    /// Execute the statements in a new scope, forgetting the variables defined by them afterwards.
+    Block(Vec<Stmt>),
+    /// Jump to a subroutine, remembering where to resume.
+    Call(BoxExpr),
+    /// Resume after the latest `Call`.
+    Return,
+    /// Stop the program.
+    Halt,
+    /// Assign a value to a variable which may not be assigned again.
+    ConstAssignment(Token, BoxExpr),
+    /// Execute the body of the case matching the value of the expression,
+    /// or the default body if no case matches.
+    Switch(BoxExpr, Vec<(u32, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    /// Do nothing, only taking up a position.
+    Nop,
+    /// Evaluate an expression, whose value is the value of the statement.
+    Expression(BoxExpr),
+    /// Write the variables and registers to the output, for debugging.
+    Dump,
 }
 
+/// Renders the statement as canonical source, see [`PrettyPrinter`].
 impl Display for Stmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let val = match self.clone() {
-            Stmt::Assignment(var, expr) => format!("{} := {}", var, expr),
-            Stmt::Store(lhs, rhs) => format!("Store({}, {})", lhs, rhs),
-            Stmt::Goto(statement) => format!("Goto {}", statement),
-            Stmt::Assert(expr) => format!("Assert {}", expr),
-            Stmt::IfThenElse(cond, iftrue, iffalse) => {
-                format!("If {} Then Goto {} Else Goto {}", cond, iftrue, iffalse)
-            }
-        };
-
-        write!(f, "{}", val)
+        write!(f, "{}", PrettyPrinter.visit_stmt(self))
     }
 }
 
 /// Expressions evaluate to values.
-#[derive(Debug, Clone)]
-pub enum Expr {
-    /// Load a value from a registry stored by `Stmt::Store`.
-    Load(BoxExpr),
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExprKind {
+    /// Load a value from a registry stored by `StmtKind::Store`.
+    /// Only the low bits of the value are loaded, according to the width.
+    Load(BoxExpr, WordWidth),
     /// A binary operator, e.g. `+`.
     Binary(BoxExpr, Token, BoxExpr),
     /// A unary operator, such as `!`.
     Unary(Token, BoxExpr),
-    /// A variable.
-    Var(String),
-    /// Load a value from some source, such as `stdin`.
-    GetInput(String),
+    /// A variable, along with the identifier token naming it.
+    Var(Token),
+    /// Load a value from an input channel, `stdin`, `argv` or `env`,
+    /// along with the name of the variable to read from `env`.
+    GetInput(String, Option<String>),
     /// A value. All simpIL values are 32-bit unsigned integers.
     Val(u32),
+    /// A floating point value.
+    Float(f64),
+    /// A string value.
+    Str(String),
+    /// Evaluate one of two expressions, depending on whether the first is nonzero.
+    Conditional(BoxExpr, BoxExpr, BoxExpr),
+    /// Apply a built-in function, named by the identifier token, to its arguments.
+    Call(Token, Vec<Expr>),
+    /// An array of integers, e.g. `[1, 2, 3]`.
+    Array(Vec<Expr>),
+    /// An element of an array, counted from `0`, e.g. `a[i]`.
+    Index(BoxExpr, BoxExpr),
+    /// The address of a named register, e.g. `@r0`.
+    Register(String),
+    /// Assign a value to a variable, giving the assigned value.
+    /// Only the value of an assignment can be one, as in `a := b := 5`.
+    Assign(Token, BoxExpr),
 }
 
+/// Renders the expression as canonical source, see [`PrettyPrinter`].
 impl Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let val = match self.clone() {
-            Expr::Load(reg) => format!("Load({})", reg),
-            Expr::Binary(lhs, op, rhs) => format!("({}, {}, {})", lhs, op, rhs),
-            Expr::Unary(op, rhs) => format!("Unary({}, {})", op, rhs),
-            Expr::Var(var) => format!("{}", var),
-            Expr::GetInput(input) => format!("GetInput({})", input),
-            Expr::Val(val) => format!("{}", val),
-        };
-
-        write!(f, "{}", val)
+        write!(f, "{}", PrettyPrinter.visit_expr(self))
     }
 }
@@ -1,10 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display};
 
 /// The TokenType encapsulates most information about a Token.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum TokenType {
     /// Tokens which are not recognized by the Scanner.
-    Invalid(u8),
+    Invalid(char),
     /// Tokens such as whitespace, which are recognized but syntactically unimportant.
     Ignore,
 
@@ -12,27 +13,91 @@ pub enum TokenType {
     LeftParen,
     /// Right parenthesis.
     RightParen,
+    /// Left brace, which opens a block.
+    LeftBrace,
+    /// Right brace, which closes a block.
+    RightBrace,
+    /// Left bracket, which opens an array or an index.
+    LeftBracket,
+    /// Right bracket, which closes an array or an index.
+    RightBracket,
     /// Comma.
     Comma,
+    /// Colon, which terminates a label and separates the branches of a conditional.
+    Colon,
+    /// Question mark, which begins the branches of a conditional.
+    Question,
+    /// Semicolon, which optionally separates statements.
+    Semicolon,
     /// Plus, the addition operator.
     Plus,
     /// Minus, the subtraction operator.
     Minus,
     /// Star, the multiplication operator.
     Star,
+    /// Double star, the exponentiation operator.
+    StarStar,
     /// Slash, the division operator.
     Slash,
+    /// Percent, the remainder operator.
+    Percent,
+    /// Less than, i.e. `<`.
+    Less,
+    /// Less than or equal, i.e. `<=`.
+    LessEqual,
+    /// Greater than, i.e. `>`.
+    Greater,
+    /// Greater than or equal, i.e. `>=`.
+    GreaterEqual,
+    /// Equality, i.e. `==`.
+    EqualEqual,
+    /// Inequality, i.e. `!=`.
+    BangEqual,
+    /// Bitwise and, i.e. `&`.
+    Ampersand,
+    /// Bitwise or, i.e. `|`.
+    Pipe,
+    /// Bitwise exclusive or, i.e. `^`.
+    Caret,
+    /// Left shift, i.e. `<<`.
+    LessLess,
+    /// Right shift, i.e. `>>`.
+    GreaterGreater,
+    /// Logical conjunction, i.e. `and`.
+    And,
+    /// Logical disjunction, i.e. `or`.
+    Or,
+    /// Logical negation, i.e. `not` or `!`.
+    Not,
 
     /// Assignment, i.e. `:=`.
     Assign,
+    /// Compound assignment of a sum, i.e. `+=`.
+    PlusEqual,
+    /// Compound assignment of a difference, i.e. `-=`.
+    MinusEqual,
+    /// Compound assignment of a product, i.e. `*=`.
+    StarEqual,
+    /// Compound assignment of a quotient, i.e. `/=`.
+    SlashEqual,
 
     /// A 32-bit unsigned integer.
     Value(u32),
+    /// A floating point number, such as `1.5`.
+    Float(f64),
+    /// A string literal, with its escapes resolved.
+    Str(String),
     /// A string identifier.
     Identifier(String),
+    /// A named register such as `@r0`, without the `@`.
+    Register(String),
 
     /// A statement keyword for storing a value in a registry.
     Store,
+    /// A statement keyword for storing the low 8 bits of a value in a registry.
+    Store8,
+    /// A statement keyword for storing the low 16 bits of a value in a registry.
+    Store16,
     /// A statement keyword for moving execution to a given statement.
     Goto,
     /// A statement keyword for asserting a condition.
@@ -45,12 +110,72 @@ pub enum TokenType {
     Else,
     /// An expression keyword for loading the value from a registry.
     Load,
+    /// An expression keyword for loading the low 8 bits of a registry.
+    Load8,
+    /// An expression keyword for loading the low 16 bits of a registry.
+    Load16,
     /// A statement keyword for getting input from an external source, such as `stdin`.
     GetInput,
+    /// A statement keyword for naming a position in the program.
+    Label,
+    /// A statement keyword for writing a value to the output.
+    Print,
+    /// A statement keyword for repeating a body while a condition holds.
+    While,
+    /// The keyword which opens the body of a loop.
+    Do,
+    /// The keyword which closes the body of a loop.
+    End,
+    /// A statement keyword for jumping to a subroutine.
+    Call,
+    /// A statement keyword for resuming after the latest `call`.
+    Return,
+    /// A statement keyword for stopping the program.
+    Halt,
+    /// A statement keyword for assigning a variable once.
+    Const,
+    /// A statement keyword for branching on the value of an expression.
+    Switch,
+    /// A keyword which begins a branch of a `switch`.
+    Case,
+    /// A keyword which begins the fallback branch of a `switch`.
+    Default,
+    /// A statement keyword for doing nothing.
+    Nop,
+    /// A statement keyword for writing out the variables and registers.
+    Dump,
+}
+
+/// Render a float as a literal, in positional notation with a fractional part,
+/// since the scanner reads neither exponents nor a bare integer as a float.
+pub(crate) fn float_literal(v: f64) -> String {
+    let literal = v.to_string();
+    if literal.contains('.') {
+        literal
+    } else {
+        literal + ".0"
+    }
+}
+
+/// Render a string as a literal, escaping what the scanner unescapes.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
 }
 
 /// A wrapper for TokenType, including also the lexeme and line placement.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
     /// The specific type of token.
     pub token_type: TokenType,
@@ -58,10 +183,134 @@ pub struct Token {
     pub lexeme: String,
     /// The line at which it was found.
     pub line: usize,
+    /// The column at which it starts.
+    pub column: usize,
+}
+
+/// Renders the token as it would be written in source code.
+impl Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenType::Invalid(c) => return write!(f, "{}", c),
+            TokenType::Value(v) => return write!(f, "{}", v),
+            TokenType::Float(v) => return write!(f, "{}", float_literal(*v)),
+            TokenType::Str(s) => return write!(f, "{}", quote(s)),
+            TokenType::Identifier(i) => return write!(f, "{}", i),
+            TokenType::Register(r) => return write!(f, "@{}", r),
+            TokenType::Ignore => "",
+            TokenType::LeftParen => "(",
+            TokenType::LeftBracket => "[",
+            TokenType::RightBracket => "]",
+            TokenType::RightParen => ")",
+            TokenType::LeftBrace => "{",
+            TokenType::RightBrace => "}",
+            TokenType::Comma => ",",
+            TokenType::Colon => ":",
+            TokenType::Question => "?",
+            TokenType::Semicolon => ";",
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Star => "*",
+            TokenType::StarStar => "**",
+            TokenType::Slash => "/",
+            TokenType::Percent => "%",
+            TokenType::Less => "<",
+            TokenType::LessEqual => "<=",
+            TokenType::Greater => ">",
+            TokenType::GreaterEqual => ">=",
+            TokenType::EqualEqual => "==",
+            TokenType::BangEqual => "!=",
+            TokenType::Ampersand => "&",
+            TokenType::Pipe => "|",
+            TokenType::Caret => "^",
+            TokenType::LessLess => "<<",
+            TokenType::GreaterGreater => ">>",
+            TokenType::And => "and",
+            TokenType::Or => "or",
+            TokenType::Not => "not",
+            TokenType::Assign => ":=",
+            TokenType::PlusEqual => "+=",
+            TokenType::MinusEqual => "-=",
+            TokenType::StarEqual => "*=",
+            TokenType::SlashEqual => "/=",
+            TokenType::Store => "store",
+            TokenType::Store8 => "store8",
+            TokenType::Store16 => "store16",
+            TokenType::Goto => "goto",
+            TokenType::Assert => "assert",
+            TokenType::If => "if",
+            TokenType::Then => "then",
+            TokenType::Else => "else",
+            TokenType::Load => "load",
+            TokenType::Load8 => "load8",
+            TokenType::Load16 => "load16",
+            TokenType::GetInput => "get_input",
+            TokenType::Label => "label",
+            TokenType::Print => "print",
+            TokenType::While => "while",
+            TokenType::Do => "do",
+            TokenType::End => "end",
+            TokenType::Call => "call",
+            TokenType::Return => "return",
+            TokenType::Halt => "halt",
+            TokenType::Const => "const",
+            TokenType::Switch => "switch",
+            TokenType::Case => "case",
+            TokenType::Default => "default",
+            TokenType::Nop => "nop",
+            TokenType::Dump => "dump",
+        };
+        write!(f, "{}", text)
+    }
 }
 
 impl Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", &self.token_type)
+        write!(f, "{}", &self.token_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(token_type: TokenType) -> Token {
+        Token {
+            lexeme: token_type.to_string(),
+            token_type,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    #[test]
+    fn display_operators() {
+        assert_eq!(token(TokenType::Plus).to_string(), "+");
+        assert_eq!(token(TokenType::Assign).to_string(), ":=");
+        assert_eq!(token(TokenType::SlashEqual).to_string(), "/=");
+        assert_eq!(token(TokenType::GreaterGreater).to_string(), ">>");
+        assert_eq!(token(TokenType::Not).to_string(), "not");
+    }
+
+    #[test]
+    fn display_payloads() {
+        assert_eq!(token(TokenType::Value(42)).to_string(), "42");
+        assert_eq!(token(TokenType::Float(1.0)).to_string(), "1.0");
+        assert_eq!(token(TokenType::Identifier("x".into())).to_string(), "x");
+        assert_eq!(
+            token(TokenType::Str("a\"b\n".into())).to_string(),
+            r#""a\"b\n""#
+        );
+    }
+
+    #[test]
+    fn serialize_token_types() {
+        let json = |token_type| serde_json::to_value(token_type).unwrap();
+        assert_eq!(json(TokenType::Plus), serde_json::json!("Plus"));
+        assert_eq!(json(TokenType::Value(1)), serde_json::json!({ "Value": 1 }));
+        assert_eq!(
+            json(TokenType::Register("r0".into())),
+            serde_json::json!({ "Register": "r0" })
+        );
     }
 }
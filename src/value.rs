@@ -0,0 +1,158 @@
+use crate::interpreter::RuntimeError;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A value computed at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A 32-bit unsigned integer, the native simpIL value.
+    Int(u32),
+    /// A double precision floating point number.
+    Float(f64),
+    /// A string of text.
+    Str(String),
+    /// A fixed sequence of integers.
+    Array(Vec<u32>),
+}
+
+/// The number of bits in an integer value.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum WordWidth {
+    /// 8-bit integers.
+    U8,
+    /// 16-bit integers.
+    U16,
+    /// 32-bit integers, the native simpIL width.
+    #[default]
+    U32,
+}
+
+impl WordWidth {
+    /// The suffix of the `load` and `store` keywords accessing this width,
+    /// which is empty for whole words.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            WordWidth::U8 => "8",
+            WordWidth::U16 => "16",
+            WordWidth::U32 => "",
+        }
+    }
+
+    /// The largest integer of this width, which is also the mask of its bits.
+    pub fn max(self) -> u32 {
+        match self {
+            WordWidth::U8 => u8::MAX as u32,
+            WordWidth::U16 => u16::MAX as u32,
+            WordWidth::U32 => u32::MAX,
+        }
+    }
+}
+
+/// The base in which integers are written to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// Decimal, e.g. `31`.
+    #[default]
+    Dec,
+    /// Hexadecimal, e.g. `0x1f`.
+    Hex,
+    /// Binary, e.g. `0b11111`.
+    Bin,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dec" => Ok(OutputFormat::Dec),
+            "hex" => Ok(OutputFormat::Hex),
+            "bin" => Ok(OutputFormat::Bin),
+            _ => Err(format!("unknown format '{}', expected dec, hex or bin", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    fn int(self, v: u32) -> String {
+        match self {
+            OutputFormat::Dec => v.to_string(),
+            OutputFormat::Hex => format!("{:#x}", v),
+            OutputFormat::Bin => format!("{:#b}", v),
+        }
+    }
+}
+
+impl Value {
+    /// Render the value like `Display`, but with integers in `format`.
+    pub fn render(&self, format: OutputFormat) -> String {
+        match self {
+            Value::Int(v) => format.int(*v),
+            Value::Array(v) => {
+                let items: Vec<_> = v.iter().map(|&item| format.int(item)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    /// The integer held by this value, or an error if it is not an integer.
+    pub fn as_int(&self) -> Result<u32, RuntimeError> {
+        match self {
+            Value::Int(v) => Ok(*v),
+            _ => Err(RuntimeError::ExpectedInt(self.clone())),
+        }
+    }
+
+    /// This value as a float, promoting integers, or an error if it is not a number.
+    pub fn as_float(&self) -> Result<f64, RuntimeError> {
+        match self {
+            Value::Int(v) => Ok(*v as f64),
+            Value::Float(v) => Ok(*v),
+            _ => Err(RuntimeError::ExpectedNumber(self.clone())),
+        }
+    }
+
+    /// True unless the value is zero, the empty string or the empty array.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Int(v) => *v != 0,
+            Value::Float(v) => *v != 0.0,
+            Value::Str(v) => !v.is_empty(),
+            Value::Array(v) => !v.is_empty(),
+        }
+    }
+}
+
+impl From<u32> for Value {
+    fn from(v: u32) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Int(v as u32)
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Str(v)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{:?}", v),
+            Value::Str(v) => write!(f, "{}", v),
+            Value::Array(v) => {
+                let items: Vec<_> = v.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use crate::syntax::{Expr, Stmt};
+
+/// A traversal over the simpIL syntax tree, producing a `T` for every node.
+///
+/// Implementors decide whether and how to recurse into child nodes.
+pub trait Visitor<T> {
+    /// Visit a single statement.
+    fn visit_stmt(&mut self, s: &Stmt) -> T;
+    /// Visit a single expression.
+    fn visit_expr(&mut self, e: &Expr) -> T;
+}
@@ -0,0 +1,324 @@
+use crate::compile::Op;
+use crate::interpreter::{assertion, element, Interpreter, InterpreterOutcome, RuntimeError};
+use crate::value::Value;
+
+/// Executes the instructions produced by [`compile`](../compile/fn.compile.html) over a stack.
+///
+/// Variables, memory, input and output are kept in an [`Interpreter`],
+/// so the options it is created with, such as a memory model or an output limit, apply.
+/// The results are the same as those of the tree-walking interpreter,
+/// one value for each executed top-level statement which produces one.
+pub struct Vm {
+    ops: Vec<Op>,
+    starts: Vec<usize>,
+    state: Interpreter,
+    stack: Vec<Value>,
+    call_stack: Vec<usize>,
+    results: Vec<Value>,
+}
+
+impl Vm {
+    pub fn new(ops: Vec<Op>) -> Self {
+        Self::with_state(ops, Interpreter::new(vec![]))
+    }
+
+    /// Create a VM which keeps its variables, memory, input and output in `state`.
+    pub fn with_state(ops: Vec<Op>, state: Interpreter) -> Self {
+        let mut starts: Vec<_> = ops
+            .iter()
+            .enumerate()
+            .filter(|(_, op)| matches!(op, Op::Statement(_)))
+            .map(|(address, _)| address)
+            .collect();
+        starts.push(ops.len());
+        Self {
+            ops,
+            starts,
+            state,
+            stack: Vec::new(),
+            call_stack: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Run the program, returning the value of each executed top-level statement
+    /// which produces one.
+    pub fn run(mut self) -> Result<Vec<Value>, RuntimeError> {
+        self.execute()?;
+        Ok(self.results)
+    }
+
+    /// Run the program, returning the final variables and registers along with the results.
+    pub fn run_to_completion(mut self) -> Result<InterpreterOutcome, RuntimeError> {
+        self.execute()?;
+        let results = std::mem::take(&mut self.results);
+        Ok(self.state.outcome(results))
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        let ops = std::mem::take(&mut self.ops);
+        let mut pc = 0;
+        while let Some(op) = ops.get(pc) {
+            pc += 1;
+            match op {
+                Op::Statement(index) => self.state.begin(*index)?,
+                Op::Step => self.state.step()?,
+                Op::Push(value) => self.stack.push(value.clone()),
+                Op::Literal(value, line) => {
+                    let value = self.state.literal(*value, *line)?;
+                    self.stack.push(value);
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Result => {
+                    let value = self.pop();
+                    self.results.push(value);
+                }
+                Op::CheckConst(name) => self.state.check_const(name)?,
+                Op::Assign(name, constant) => {
+                    let value = self.pop();
+                    let value = self.state.define(name, value, *constant)?;
+                    self.stack.push(value);
+                }
+                Op::Get(name) => {
+                    let value = self.state.variable(name)?;
+                    self.stack.push(value);
+                }
+                Op::Register(name) => {
+                    return Err(RuntimeError::NamedRegisterValue(name.clone()));
+                }
+                Op::Target(name) => {
+                    let value = self
+                        .state
+                        .variable(name)
+                        .map_err(|_| RuntimeError::UndefinedLabel(name.clone()))?;
+                    self.stack.push(value);
+                }
+                Op::AsInt => {
+                    self.top().as_int()?;
+                }
+                Op::Store(width) => {
+                    let value = self.pop();
+                    let reg = self.pop().as_int()?;
+                    let value = self.state.store(reg, value, *width)?;
+                    self.stack.push(value);
+                }
+                Op::Load(width) => {
+                    let reg = self.pop().as_int()?;
+                    let value = self.state.load(reg, *width)?;
+                    self.stack.push(value);
+                }
+                Op::StoreNamed(name, width) => {
+                    let value = self.pop();
+                    let value = self.state.store_named(name, value, *width)?;
+                    self.stack.push(value);
+                }
+                Op::LoadNamed(name, width) => {
+                    let value = self.state.load_named(name, *width)?;
+                    self.stack.push(value);
+                }
+                Op::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = self.state.binary(op, lhs, rhs)?;
+                    self.stack.push(value);
+                }
+                Op::Unary(op) => {
+                    let value = self.pop();
+                    let value = self.state.unary(op, value)?;
+                    self.stack.push(value);
+                }
+                Op::Truthy => {
+                    let value = self.pop().is_truthy();
+                    self.stack.push(value.into());
+                }
+                Op::Builtin(name, count) => {
+                    let args = self.stack.split_off(self.stack.len() - count);
+                    let value = self.state.builtin(name, &args)?;
+                    self.stack.push(value);
+                }
+                Op::Array(count) => {
+                    let items = self
+                        .stack
+                        .split_off(self.stack.len() - count)
+                        .iter()
+                        .map(Value::as_int)
+                        .collect::<Result<_, _>>()?;
+                    self.stack.push(Value::Array(items));
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let array = self.pop();
+                    self.stack.push(element(array, index)?);
+                }
+                Op::Input(channel, key) => {
+                    let value = self.state.input(channel, key.as_deref())?;
+                    self.stack.push(value);
+                }
+                Op::Print => {
+                    let value = self.pop();
+                    let value = self.state.print(value)?;
+                    self.stack.push(value);
+                }
+                Op::Dump => self.state.dump()?,
+                Op::Assert(line) => {
+                    let value = self.pop();
+                    self.stack.push(assertion(*line, value)?);
+                }
+                Op::AssertCompare(line, op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = self.state.compare(*line, op, lhs, rhs)?;
+                    self.stack.push(value);
+                }
+                Op::Jump(address) => pc = *address,
+                Op::JumpUnless(address) => {
+                    if !self.pop().is_truthy() {
+                        pc = *address;
+                    }
+                }
+                Op::Branch(address) => match self.pop() {
+                    Value::Int(1) => (),
+                    Value::Int(0) => pc = *address,
+                    cond => return Err(RuntimeError::NonBooleanCondition(cond)),
+                },
+                Op::Case(value, address) => {
+                    if self.top().as_int()? == *value {
+                        self.pop();
+                    } else {
+                        pc = *address;
+                    }
+                }
+                Op::EnterScope => self.state.enter_scope(),
+                Op::ExitScope => self.state.exit_scope(),
+                Op::Goto => {
+                    let target = self.pop().as_int()?;
+                    pc = self.goto(target)?;
+                }
+                Op::Call { resume } => {
+                    let target = self.pop().as_int()?;
+                    self.call_stack.push(*resume);
+                    pc = self.goto(target)?;
+                }
+                Op::Return => {
+                    let target = self
+                        .call_stack
+                        .pop()
+                        .ok_or(RuntimeError::ReturnWithoutCall)?;
+                    pc = self.goto(target as u32)?;
+                }
+                Op::Halt => {
+                    self.state.exit_scopes();
+                    pc = ops.len();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the start of the statement `target`, leaving any nested scopes.
+    fn goto(&mut self, target: u32) -> Result<usize, RuntimeError> {
+        let address = *self
+            .starts
+            .get(target as usize)
+            .ok_or(RuntimeError::InvalidJumpTarget(target))?;
+        self.state.exit_scopes();
+        self.stack.clear();
+        Ok(address)
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+
+    fn top(&self) -> &Value {
+        self.stack.last().expect("the compiler balances the stack")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile;
+    use crate::{parser::Parser, scanner::Scanner};
+    use std::io;
+
+    /// Run the source with both the tree-walking interpreter and the VM.
+    fn both(
+        src: &str,
+    ) -> (
+        Result<InterpreterOutcome, RuntimeError>,
+        Result<InterpreterOutcome, RuntimeError>,
+    ) {
+        let stmts: Vec<_> = Parser::new(Scanner::new(src)).collect();
+        let tree = Interpreter::with_writer(stmts.clone(), io::sink()).run_to_completion();
+        let state = Interpreter::with_writer(vec![], io::sink());
+        let vm = Vm::with_state(compile(&stmts), state).run_to_completion();
+        (tree, vm)
+    }
+
+    fn assert_same(src: &str) {
+        let (tree, vm) = both(src);
+        assert_eq!(vm, tree, "{}", src);
+    }
+
+    #[test]
+    fn vm_matches_interpreter() {
+        assert_same("i := 0 s := 0 while i < 10 do i := i + 1 s := s + i * i end");
+        assert_same("n := 10 a := 0 b := 1 label loop: t := a + b a := b b := t n := n - 1 if n > 0 then goto loop else goto 9");
+        assert_same("x := 1 call sub x := x + 1 halt label sub: x := x * 10 return");
+        assert_same("store(1, 7) store8(2, 300) y := load(1) + load16(2)");
+        assert_same(
+            "x := 2 switch x { case 1: y := 1 case 2: y := 2 default: y := 3 } z := [1, 2, 3][x]",
+        );
+        assert_same("x := 1 { y := 2 x := x + y } const c := x * 2.5 s := \"a\" + \"b\"");
+        assert_same(
+            "i := 0 while 1 do { j := i i := i + 1 if i < 3 then goto 1 else goto 2 } end x := i",
+        );
+        assert_same("x := 0 and 1 / 0 y := 1 or 1 / 0 z := x ? 10 : max(3, 4)");
+        assert_same("x := 4 x * 2 nop x");
+        assert_same("store(@r0, 5) store8(@r1, load(@r0) + 255) x := load(@r1)");
+        assert_same("x := 1 dump y := 2");
+        assert_same("a := b := c := 5 d := a + b + c");
+        assert_same("x := pc() while steps() < 10 do { y := pc() } end z := steps() + pc()");
+        assert_same("if 1 then x := 2 else x := 3 if x > 2 then y := 1 else goto 4 y := 2");
+        assert_same("x := 0 if x then goto 0 else x := 1 if x then halt else goto 0");
+    }
+
+    #[test]
+    fn vm_matches_interpreter_errors() {
+        assert_same("x := 1 / 0");
+        assert_same("if 5 then goto 1 else goto 2");
+        assert_same("goto 7");
+        assert_same("goto nowhere");
+        assert_same("goto MAX_U8");
+        assert_same("x := load(@r0)");
+        assert_same("return");
+        assert_same("const x := 1 x := 2");
+        assert_same("assert 1 assert 0");
+        assert_same("x := 2 assert x + 1 == 3 assert x < 1");
+    }
+
+    #[test]
+    fn vm_step_limit() {
+        let stmts: Vec<_> = Parser::new(Scanner::new("while 1 do x := 1 end")).collect();
+        let state = Interpreter::with_step_limit(vec![], 100);
+        let res = Vm::with_state(compile(&stmts), state).run();
+        assert_eq!(res, Err(RuntimeError::StepLimitExceeded(100)));
+    }
+
+    #[test]
+    fn vm_literal_too_wide() {
+        let stmts: Vec<_> = Parser::new(Scanner::new("x := 255 y := 256")).collect();
+        let state = Interpreter::with_word_width(vec![], crate::value::WordWidth::U8);
+        let res = Vm::with_state(compile(&stmts), state).run();
+        assert_eq!(
+            res,
+            Err(RuntimeError::LiteralTooWide {
+                line: 1,
+                value: 256
+            })
+        );
+    }
+}
@@ -0,0 +1,18 @@
+use simpilrs::{run_source, Value};
+
+#[test]
+fn run_source_from_outside_the_crate() {
+    assert_eq!(run_source("x := 2 + 3").unwrap(), vec![Value::Int(5)]);
+}
+
+#[test]
+fn run_source_reports_errors() {
+    let err = run_source("x := 1 / 0").unwrap_err();
+    assert_eq!(err.to_string(), "[line 1] division by zero");
+}
+
+#[test]
+fn run_source_reports_parse_errors() {
+    assert!(run_source("x := := 1").is_err());
+    assert!(run_source("x := 1 )").is_err());
+}